@@ -0,0 +1,72 @@
+/*
+Перебор циклов: прежний depth_first_search + triangle_sorting + remove_duplicates
+против CurrencyGraph::cycles на синтетическом рынке, где каждая alt торгуется
+к каждой базовой валюте, а базовые - между собой.
+*/
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use ws::brain::fixed::Fixed;
+use ws::brain::graph::{AssetRole, CurrencyGraph};
+use ws::brain::{depth_first_search, get_nodes_by_label, remove_duplicates, triangle_sorting};
+use ws::brain_sets::{AltCurrency, BaseCurrency, ParsedPairs, Template};
+
+const BASES: [&str; 4] = ["USDT", "BTC", "ETH", "BNB"];
+
+fn market(alts: usize) -> CurrencyGraph {
+    let base: Vec<BaseCurrency> = BASES
+        .iter()
+        .map(|symbol| BaseCurrency {
+            symbol: symbol.to_string(),
+            percentage: 25.0,
+            min_earn: None,
+        })
+        .collect();
+    let mut alt: Vec<AltCurrency> = (0..alts)
+        .map(|i| AltCurrency {
+            symbol: format!("A{}X", i),
+        })
+        .collect();
+    // базовые тоже торгуются друг к другу: BTCUSDT, ETHBTC, ...
+    alt.extend(BASES[1..].iter().map(|symbol| AltCurrency {
+        symbol: symbol.to_string(),
+    }));
+
+    let template = Template { ixs: 0, ixe: 0 };
+    let mut clean = Vec::new();
+    for a in &alt {
+        for b in &base {
+            if a.symbol != b.symbol {
+                clean.push(ParsedPairs::new(
+                    format!("{}{}", a.symbol, b.symbol),
+                    template.clone(),
+                    template.clone(),
+                    template.clone(),
+                ));
+            }
+        }
+    }
+    CurrencyGraph::build(&base, &alt, &clean, None, Fixed::ZERO)
+}
+
+fn cycles(c: &mut Criterion) {
+    let mut group = c.benchmark_group("triangles");
+    group.sample_size(10);
+    for alts in [100, 500, 2000] {
+        let graph = market(alts);
+        group.bench_with_input(BenchmarkId::new("legacy_dfs", alts), &graph, |b, graph| {
+            b.iter(|| {
+                let base_nodes = get_nodes_by_label(graph.graph(), AssetRole::Base);
+                let all = depth_first_search(graph.graph(), &base_nodes, 3)
+                    .expect("base nodes come from the graph");
+                black_box(remove_duplicates(triangle_sorting(all)).len())
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("canonical", alts), &graph, |b, graph| {
+            b.iter(|| black_box(graph.cycles(3).count()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cycles);
+criterion_main!(benches);
@@ -0,0 +1,468 @@
+/*
+Распределение капитала и пороги по базовым валютам.
+
+BaseCurrency::percentage - доля общего капитала (capital, в capital_asset),
+выделенная под треугольники, которые начинаются с этой базовой валюты.
+BaseCurrency::min_earn - свой порог earn для таких треугольников.
+
+Рекомендуемый объем сделки - минимум из выделенного капитала (в стартовой
+валюте) и объема, который пропускают все три ноги по текущим bid_qty/ask_qty.
+Без капитала треугольник с правилами символов прогоняется на наименьшем
+объеме, который пропускают min_qty и min notional всех ног.
+
+Все цены берутся из снимка тика (spm), в том числе для пересчета капитала
+в стартовую валюту: снимок должен включать conversion_pairs. Пара пересчета -
+ребро графа capital_asset -> базовая валюта, символ по склейке не угадывается.
+*/
+use bigdecimal::{BigDecimal, FromPrimitive, Zero};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use super::fixed::Fixed;
+use super::graph::{CurrencyGraph, Side};
+use super::observer::PriceUpdate;
+use super::triangle::TriangleKey;
+use crate::brain_sets::BaseCurrency;
+use crate::symbols::SymbolRegistry;
+
+const SCALE: i64 = 10;
+const MIN_SIZE_MARGIN: &str = "1.01"; // запас к наименьшему объему на комиссии ног
+
+struct BaseRule {
+    share: BigDecimal,       // доля капитала, 0..1
+    min_earn: Option<Fixed>, // %
+}
+
+pub struct Allocator {
+    rules: HashMap<String, BaseRule>,
+    capital: BigDecimal,
+    capital_asset: String,
+    conversions: HashMap<String, (String, Side)>, // базовая валюта - пара и сторона из capital_asset
+}
+
+impl Allocator {
+    pub fn new(
+        base: &[BaseCurrency],
+        capital: f64,
+        capital_asset: &str,
+        graph: &CurrencyGraph,
+    ) -> Self {
+        let rules = base
+            .iter()
+            .map(|b| {
+                let rule = BaseRule {
+                    share: decimal(b.percentage).unwrap_or_default() / BigDecimal::from(100),
+                    min_earn: b
+                        .min_earn
+                        .and_then(decimal)
+                        .and_then(|earn| Fixed::from_bigdecimal(&earn)),
+                };
+                (b.symbol.clone(), rule)
+            })
+            .collect();
+        let conversions = base
+            .iter()
+            .filter(|b| b.symbol != capital_asset)
+            .filter_map(|b| {
+                let edge = graph.edge(capital_asset, &b.symbol)?;
+                Some((b.symbol.clone(), (edge.pair.symbol.clone(), edge.side)))
+            })
+            .collect();
+
+        Allocator {
+            rules,
+            capital: BigDecimal::from_f64(capital).unwrap_or_default(),
+            capital_asset: capital_asset.to_string(),
+            conversions,
+        }
+    }
+
+    // символы, цены которых нужны для пересчета капитала в стартовые валюты
+    pub fn conversion_pairs(&self) -> impl Iterator<Item = &String> {
+        self.conversions.values().map(|(symbol, _)| symbol)
+    }
+
+    // стартовая валюта треугольника, если под нее есть правило BaseCurrency
+    pub fn start_of<'a>(&self, key: &'a TriangleKey) -> Option<&'a str> {
+        self.rules
+            .contains_key(&key.start)
+            .then_some(key.start.as_str())
+    }
+
+    // порог earn для треугольника: свой у базовой валюты или общий
+    pub fn threshold(&self, key: &TriangleKey, default: Fixed) -> Fixed {
+        self.start_of(key)
+            .and_then(|start| self.rules.get(start))
+            .and_then(|rule| rule.min_earn)
+            .unwrap_or(default)
+    }
+
+    // рекомендуемый объем в стартовой валюте треугольника
+    pub fn order_size(
+        &self,
+        spm: &HashMap<String, PriceUpdate>,
+        key: &TriangleKey,
+        legs: &[(String, String)],
+    ) -> Option<BigDecimal> {
+        let start = self.start_of(key)?;
+        let allotted = self.allotted_in(spm, start)?;
+        let capacity = leg_capacity(spm, legs)?;
+        Some(allotted.min(capacity).with_scale(SCALE))
+    }
+
+    // объем, на котором треугольник прогоняется по правилам символов
+    pub fn trial_size(
+        &self,
+        spm: &HashMap<String, PriceUpdate>,
+        key: &TriangleKey,
+        legs: &[(String, String)],
+        registry: &SymbolRegistry,
+    ) -> Option<BigDecimal> {
+        if self.capital.is_zero() {
+            return min_order_size(spm, legs, registry);
+        }
+        self.order_size(spm, key, legs)
+    }
+
+    // выделенный под валюту капитал, пересчитанный в нее саму по ценам тика
+    fn allotted_in(&self, spm: &HashMap<String, PriceUpdate>, asset: &str) -> Option<BigDecimal> {
+        let share = &self.rules.get(asset)?.share;
+        let allotted = &self.capital * share;
+        if asset == self.capital_asset {
+            return Some(allotted);
+        }
+        // BTCUSDT: покупаем BTC за USDT по ask; USDTTRY: продаем USDT по bid
+        let (symbol, side) = self.conversions.get(asset)?;
+        let update = spm.get(symbol)?;
+        match side {
+            Side::Buy => {
+                let ask = BigDecimal::from_str(&update.ask).ok()?;
+                (!ask.is_zero()).then(|| (allotted / ask).with_scale(SCALE))
+            }
+            Side::Sell => {
+                let bid = BigDecimal::from_str(&update.bid).ok()?;
+                Some((allotted * bid).with_scale(SCALE))
+            }
+        }
+    }
+}
+
+/*
+Сколько стартовой валюты пропустят все ноги. multiplier - сколько единиц
+текущей валюты получается из единицы стартовой к началу ноги.
+SELL продает базовую валюту пары, доступно bid_qty.
+BUY тратит котируемую валюту, доступно ask_qty * ask.
+*/
+fn leg_capacity(
+    spm: &HashMap<String, PriceUpdate>,
+    legs: &[(String, String)],
+) -> Option<BigDecimal> {
+    let mut multiplier = BigDecimal::from(1);
+    let mut capacity: Option<BigDecimal> = None;
+
+    for (pair, dir) in legs {
+        let update = spm.get(pair)?;
+        let (available, next) = if dir == "SELL" {
+            let bid = BigDecimal::from_str(&update.bid).ok()?;
+            let qty = BigDecimal::from_str(&update.bid_qty).ok()?;
+            (qty, &multiplier * bid)
+        } else {
+            let ask = BigDecimal::from_str(&update.ask).ok()?;
+            let qty = BigDecimal::from_str(&update.ask_qty).ok()?;
+            if ask.is_zero() {
+                return None;
+            }
+            (qty * &ask, (&multiplier / ask).with_scale(SCALE))
+        };
+        if multiplier.is_zero() {
+            return None;
+        }
+        let in_start = (available / &multiplier).with_scale(SCALE);
+        capacity = Some(match capacity {
+            Some(current) => current.min(in_start),
+            None => in_start,
+        });
+        multiplier = next.with_scale(SCALE);
+    }
+
+    capacity
+}
+
+/*
+Наименьший объем в стартовой валюте, при котором каждая нога набирает min_qty
+и min notional своего символа. К количеству ноги добавляется шаг лота: его
+съедает округление вниз. Символ без правил ограничений не добавляет.
+*/
+fn min_order_size(
+    spm: &HashMap<String, PriceUpdate>,
+    legs: &[(String, String)],
+    registry: &SymbolRegistry,
+) -> Option<BigDecimal> {
+    let mut multiplier = BigDecimal::from(1);
+    let mut size = BigDecimal::zero();
+
+    for (pair, dir) in legs {
+        let update = spm.get(pair)?;
+        let sell = dir == "SELL";
+        let price = BigDecimal::from_str(if sell { &update.bid } else { &update.ask }).ok()?;
+        if price.is_zero() || multiplier.is_zero() {
+            return None;
+        }
+        if let Some(info) = registry.get(pair) {
+            let qty = (info.min_notional.to_bigdecimal() / &price)
+                .with_scale(SCALE)
+                .max(info.min_qty.value().to_bigdecimal())
+                + info.step_size.value().to_bigdecimal();
+            // SELL тратит базовую валюту пары, BUY - котируемую
+            let spend = if sell { qty } else { qty * &price };
+            size = size.max((spend / &multiplier).with_scale(SCALE));
+        }
+        multiplier = if sell {
+            &multiplier * &price
+        } else {
+            &multiplier / &price
+        }
+        .with_scale(SCALE);
+    }
+
+    let margin = BigDecimal::from_str(MIN_SIZE_MARGIN).ok()?;
+    (!size.is_zero()).then(|| (size * margin).with_scale(SCALE))
+}
+
+// значение из настроек как записано: через строку, без двоичного хвоста f32 (0.3 -> 0.300000011920...)
+fn decimal(value: f32) -> Option<BigDecimal> {
+    BigDecimal::from_str(&value.to_string()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::fixed::{Price, Qty};
+    use crate::brain_sets::{AltCurrency, ParsedPairs, Template};
+    use crate::symbols::SymbolInfo;
+
+    fn update(symbol: &str, bid: &str, ask: &str) -> (String, PriceUpdate) {
+        let update = PriceUpdate {
+            symbol: symbol.to_string(),
+            bid: bid.to_string(),
+            ask: ask.to_string(),
+            bid_qty: "1000".to_string(),
+            ask_qty: "1000".to_string(),
+            exchange_ts: None,
+            recv_ts: 0,
+            trace: Default::default(),
+        };
+        (symbol.to_string(), update)
+    }
+
+    fn info(symbol: &str, base: &str, quote: &str, min_notional: &str) -> SymbolInfo {
+        SymbolInfo {
+            symbol: symbol.to_string(),
+            base_asset: base.to_string(),
+            quote_asset: quote.to_string(),
+            tick_size: Price::new(Fixed::parse("0.00001").unwrap()),
+            step_size: Qty::new(Fixed::parse("0.001").unwrap()),
+            min_qty: Qty::new(Fixed::parse("0.001").unwrap()),
+            min_notional: Fixed::parse(min_notional).unwrap(),
+        }
+    }
+
+    // USDT -> BTC -> ETH -> USDT
+    fn market() -> (
+        HashMap<String, PriceUpdate>,
+        Vec<(String, String)>,
+        SymbolRegistry,
+    ) {
+        let spm = HashMap::from([
+            update("BTCUSDT", "99", "100"),
+            update("ETHBTC", "0.049", "0.05"),
+            update("ETHUSDT", "5", "5.1"),
+        ]);
+        let legs = [("BTCUSDT", "BUY"), ("ETHBTC", "BUY"), ("ETHUSDT", "SELL")]
+            .iter()
+            .map(|(pair, dir)| (pair.to_string(), dir.to_string()))
+            .collect();
+        let mut registry = SymbolRegistry::default();
+        registry.insert(info("BTCUSDT", "BTC", "USDT", "5"));
+        registry.insert(info("ETHBTC", "ETH", "BTC", "0.0001"));
+        registry.insert(info("ETHUSDT", "ETH", "USDT", "5"));
+        (spm, legs, registry)
+    }
+
+    // базовые валюты - котируемые валюты правил, alt - их базовые валюты
+    fn graph(registry: &SymbolRegistry) -> CurrencyGraph {
+        let template = Template { ixs: 0, ixe: 0 };
+        let mut clean = Vec::new();
+        let mut base = Vec::new();
+        let mut alt = Vec::new();
+        for info in registry.iter() {
+            clean.push(ParsedPairs::new(
+                info.symbol.clone(),
+                template.clone(),
+                template.clone(),
+                template.clone(),
+            ));
+            base.push(BaseCurrency {
+                symbol: info.quote_asset.clone(),
+                percentage: 0.0,
+                min_earn: None,
+            });
+            alt.push(AltCurrency {
+                symbol: info.base_asset.clone(),
+            });
+        }
+        CurrencyGraph::build(&base, &alt, &clean, Some(registry), Fixed::ZERO)
+    }
+
+    fn key() -> TriangleKey {
+        TriangleKey {
+            a: "BTCUSDT".to_string(),
+            b: "ETHBTC".to_string(),
+            c: "ETHUSDT".to_string(),
+            d: "BUY".to_string(),
+            start: "USDT".to_string(),
+        }
+    }
+
+    fn base(percentage: f32) -> Vec<BaseCurrency> {
+        vec![BaseCurrency {
+            symbol: "USDT".to_string(),
+            percentage,
+            min_earn: None,
+        }]
+    }
+
+    #[test]
+    fn without_capital_trial_size_is_the_smallest_accepted_order() {
+        let (spm, legs, registry) = market();
+        let allocator = Allocator::new(&base(100.0), 0.0, "USDT", &graph(&registry));
+
+        // BTCUSDT: 5 / 100 + шаг = 0.051 BTC = 5.1 USDT; остальные ноги требуют меньше
+        let size = allocator.trial_size(&spm, &key(), &legs, &registry);
+        assert_eq!(size, Some(BigDecimal::from_str("5.151").unwrap()));
+    }
+
+    #[test]
+    fn with_capital_trial_size_is_the_order_size() {
+        let (spm, legs, registry) = market();
+        let allocator = Allocator::new(&base(50.0), 1000.0, "USDT", &graph(&registry));
+
+        let size = allocator.trial_size(&spm, &key(), &legs, &registry);
+        assert_eq!(size, allocator.order_size(&spm, &key(), &legs));
+        assert_eq!(size, Some(BigDecimal::from(500)));
+    }
+
+    #[test]
+    fn threshold_is_per_base_currency_with_a_default() {
+        let (_, _, registry) = market();
+        let mut rules = base(100.0);
+        rules[0].min_earn = Some(0.5);
+        let allocator = Allocator::new(&rules, 0.0, "USDT", &graph(&registry));
+        let default = Fixed::parse("0.1").unwrap();
+
+        assert_eq!(
+            allocator.threshold(&key(), default),
+            Fixed::parse("0.5").unwrap()
+        );
+        let other = TriangleKey {
+            start: "BTC".to_string(),
+            ..key()
+        };
+        assert_eq!(allocator.start_of(&other), None);
+        assert_eq!(allocator.threshold(&other, default), default);
+
+        let without = Allocator::new(&base(100.0), 0.0, "USDT", &graph(&registry));
+        assert_eq!(without.threshold(&key(), default), default);
+    }
+
+    #[test]
+    fn order_size_is_capped_by_leg_capacity() {
+        let (mut spm, legs, registry) = market();
+        let allocator = Allocator::new(&base(100.0), 1000.0, "USDT", &graph(&registry));
+        assert_eq!(
+            allocator.order_size(&spm, &key(), &legs),
+            Some(BigDecimal::from(1000))
+        );
+
+        // ETHBTC пропускает 10 ETH по 0.05 = 0.5 BTC = 50 USDT
+        spm.get_mut("ETHBTC").unwrap().ask_qty = "10".to_string();
+        assert_eq!(
+            allocator.order_size(&spm, &key(), &legs),
+            Some(BigDecimal::from(50))
+        );
+
+        spm.remove("ETHUSDT");
+        assert_eq!(allocator.order_size(&spm, &key(), &legs), None);
+    }
+
+    #[test]
+    fn capital_is_converted_by_tick_prices() {
+        let (mut spm, _, registry) = market();
+        // BTC -> ETH -> USDT -> BTC
+        let legs: Vec<(String, String)> =
+            [("ETHBTC", "BUY"), ("ETHUSDT", "SELL"), ("BTCUSDT", "BUY")]
+                .iter()
+                .map(|(pair, dir)| (pair.to_string(), dir.to_string()))
+                .collect();
+        let btc = TriangleKey {
+            start: "BTC".to_string(),
+            ..key()
+        };
+        let rules = vec![BaseCurrency {
+            symbol: "BTC".to_string(),
+            percentage: 33.3,
+            min_earn: None,
+        }];
+        let allocator = Allocator::new(&rules, 1000.0, "USDT", &graph(&registry));
+        let conversions: Vec<&String> = allocator.conversion_pairs().collect();
+        assert_eq!(conversions, ["BTCUSDT"]);
+
+        // 333 USDT по ask 100; доля без хвоста f32
+        assert_eq!(
+            allocator.order_size(&spm, &btc, &legs),
+            Some(BigDecimal::from_str("3.33").unwrap())
+        );
+
+        // капитал в BTC, старт в USDT: пары USDTBTC нет, продаем BTC по bid BTCUSDT
+        let allocator = Allocator::new(&base(100.0), 1.0, "BTC", &graph(&registry));
+        let (_, usdt_legs, _) = market();
+        assert_eq!(
+            allocator.order_size(&spm, &key(), &usdt_legs),
+            Some(BigDecimal::from(99))
+        );
+
+        // без цены пересчета в снимке объема нет
+        spm.remove("BTCUSDT");
+        let allocator = Allocator::new(&rules, 1000.0, "USDT", &graph(&registry));
+        assert_eq!(allocator.order_size(&spm, &btc, &legs), None);
+    }
+
+    #[test]
+    fn min_earn_is_taken_as_written() {
+        let (_, _, registry) = market();
+        let mut rules = base(100.0);
+        rules[0].min_earn = Some(0.3);
+        let allocator = Allocator::new(&rules, 0.0, "USDT", &graph(&registry));
+        assert_eq!(
+            allocator.threshold(&key(), Fixed::ZERO),
+            Fixed::parse("0.3").unwrap()
+        );
+    }
+
+    #[test]
+    fn conversion_pair_comes_from_the_graph_not_the_symbol_name() {
+        // BTCBUSD - это BTC/BUSD, а не BTCB/USD
+        let mut registry = SymbolRegistry::default();
+        registry.insert(info("BTCBUSD", "BTC", "BUSD", "5"));
+        let spm = HashMap::from([update("BTCBUSD", "99", "100")]);
+        let rules = vec![BaseCurrency {
+            symbol: "BTCB".to_string(),
+            percentage: 100.0,
+            min_earn: None,
+        }];
+        let allocator = Allocator::new(&rules, 1000.0, "USD", &graph(&registry));
+
+        assert_eq!(allocator.conversion_pairs().count(), 0);
+        assert_eq!(allocator.allotted_in(&spm, "BTCB"), None);
+    }
+}
@@ -0,0 +1,248 @@
+/*
+Выгрузка графа валют и найденных циклов в Graphviz DOT и JSON.
+
+DOT удобно смотреть на https://viz-js.com: базовые валюты выделены цветом,
+ребра циклов - жирные, каждый цикл - отдельная заметка с текущим earn.
+earn цикла - лучшая из двух сторон обхода, чистый earn движка
+(calculate_triangle) с единицы стартовой валюты, %; без цен по какой-то
+ноге его нет.
+
+Граф, опубликованный через publish, отдает HTTP /graph. Цены на ребрах -
+копия живого графа движка в момент запроса, горячий путь не ждет выгрузку.
+*/
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::RwLock;
+
+use super::fixed::Fixed;
+use super::graph::{AssetRole, CurrencyGraph, Side};
+use super::{calculate_triangle, BrainConfig, LIVE_GRAPH};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Dot,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "dot" | "gv" => Some(ExportFormat::Dot),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Dot => "text/vnd.graphviz",
+            ExportFormat::Json => "application/json",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NodeExport {
+    pub symbol: String,
+    pub role: AssetRole,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EdgeExport {
+    pub from: String,
+    pub to: String,
+    pub symbol: String,
+    pub side: Side,
+    pub guessed: bool, // base/quote угаданы по символу, без exchangeInfo
+    pub price: Option<String>,
+    pub fee: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CycleExport {
+    pub assets: Vec<String>,
+    pub pairs: Vec<String>,
+    pub earn: Option<String>, // %, только с конфигом движка
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GraphExport {
+    pub nodes: Vec<NodeExport>,
+    pub edges: Vec<EdgeExport>,
+    pub cycles: Vec<CycleExport>,
+}
+
+impl GraphExport {
+    // с конфигом движка у циклов считается earn
+    pub fn new<S: AsRef<str>>(
+        graph: &CurrencyGraph,
+        cycles: &[Vec<S>],
+        earn: Option<&BrainConfig>,
+    ) -> Self {
+        let g = graph.graph();
+        let nodes = g
+            .node_weights()
+            .map(|asset| NodeExport {
+                symbol: asset.symbol.clone(),
+                role: asset.role,
+            })
+            .collect();
+        let edges = g
+            .edge_indices()
+            .filter_map(|edge| {
+                let (from, to) = g.edge_endpoints(edge)?;
+                let weight = &g[edge];
+                Some(EdgeExport {
+                    from: g[from].symbol.clone(),
+                    to: g[to].symbol.clone(),
+                    symbol: weight.pair.symbol.clone(),
+                    side: weight.side,
+                    guessed: weight.pair.guessed,
+                    price: weight.price.as_ref().ok().map(|price| price.to_string()),
+                    fee: weight.fee.to_string(),
+                })
+            })
+            .collect();
+        let cycles = cycles
+            .iter()
+            .map(|cycle| {
+                let assets: Vec<&str> = cycle.iter().map(AsRef::as_ref).collect();
+                let pairs = graph
+                    .path_edges(&assets)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|edge| edge.pair.symbol.clone())
+                    .collect();
+                CycleExport {
+                    assets: assets.iter().map(|asset| asset.to_string()).collect(),
+                    pairs,
+                    earn: earn
+                        .and_then(|config| cycle_earn(graph, &assets, config))
+                        .map(|earn| earn.to_string()),
+                }
+            })
+            .collect();
+        GraphExport {
+            nodes,
+            edges,
+            cycles,
+        }
+    }
+
+    pub fn render(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Dot => self.to_dot(),
+            ExportFormat::Json => self.to_json(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut in_cycles = HashSet::new();
+        for cycle in &self.cycles {
+            let len = cycle.assets.len();
+            for i in 0..len {
+                let (a, b) = (&cycle.assets[i], &cycle.assets[(i + 1) % len]);
+                in_cycles.insert((a.as_str(), b.as_str()));
+                in_cycles.insert((b.as_str(), a.as_str()));
+            }
+        }
+
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph currencies {{");
+        let _ = writeln!(out, "  rankdir=LR;");
+        let _ = writeln!(out, "  node [shape=circle];");
+        for node in &self.nodes {
+            match node.role {
+                AssetRole::Base => {
+                    let _ = writeln!(
+                        out,
+                        "  \"{}\" [shape=doublecircle, style=filled, fillcolor=lightblue];",
+                        node.symbol
+                    );
+                }
+                AssetRole::Alt => {
+                    let _ = writeln!(out, "  \"{}\";", node.symbol);
+                }
+            }
+        }
+        for edge in &self.edges {
+            let mut label = format!("{} {}", edge.symbol, edge.side.as_str());
+            if edge.guessed {
+                label.push_str(" (guessed)");
+            }
+            if let Some(price) = &edge.price {
+                let _ = write!(label, "\\n{}", price);
+            }
+            let style = if in_cycles.contains(&(edge.from.as_str(), edge.to.as_str())) {
+                ", style=bold"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                out,
+                "  \"{}\" -> \"{}\" [label=\"{}\"{}];",
+                edge.from, edge.to, label, style
+            );
+        }
+        for (index, cycle) in self.cycles.iter().enumerate() {
+            let mut label = cycle.assets.join(" - ");
+            if let Some(earn) = &cycle.earn {
+                let _ = write!(label, "\\nearn {}%", earn);
+            }
+            let _ = writeln!(
+                out,
+                "  \"cycle_{}\" [shape=note, label=\"{}\"];",
+                index, label
+            );
+        }
+        let _ = writeln!(out, "}}");
+        out
+    }
+}
+
+// лучшая из двух сторон обхода, %
+fn cycle_earn(graph: &CurrencyGraph, assets: &[&str], config: &BrainConfig) -> Option<Fixed> {
+    let mut reversed = assets.to_vec();
+    reversed.reverse();
+    [assets.to_vec(), reversed]
+        .iter()
+        .filter_map(|path| graph.legs(path))
+        .filter_map(|legs| calculate_triangle(graph, &legs, None, config).ok())
+        .map(|evaluation| evaluation.net_earn.truncate(config.earn_scale))
+        .max()
+}
+
+struct Published {
+    graph: CurrencyGraph,
+    cycles: Vec<Vec<String>>,
+    config: BrainConfig,
+}
+
+lazy_static::lazy_static! {
+    static ref PUBLISHED: RwLock<Option<Published>> = RwLock::new(None);
+}
+
+// граф и циклы (после remove_duplicates), которые отдает HTTP /graph; earn - по config
+pub fn publish(graph: CurrencyGraph, cycles: Vec<Vec<String>>, config: &BrainConfig) {
+    *PUBLISHED.write().unwrap() = Some(Published {
+        graph,
+        cycles,
+        config: config.clone(),
+    });
+}
+
+// опубликованный граф с ценами движка; None - ничего не опубликовано
+pub fn published(with_earn: bool) -> Option<GraphExport> {
+    let published = PUBLISHED.read().unwrap();
+    let published = published.as_ref()?;
+    // пока движок не запущен, цен на ребрах нет
+    let live = LIVE_GRAPH.read().unwrap().clone();
+    let graph = live.as_ref().unwrap_or(&published.graph);
+    let earn = with_earn.then_some(&published.config);
+    Some(GraphExport::new(graph, &published.cycles, earn))
+}
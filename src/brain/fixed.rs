@@ -0,0 +1,320 @@
+/*
+Точная арифметика с фиксированной точкой для оценки треугольников.
+
+Fixed - целое число единиц 10^-10, т.е. ровно 10 знаков после точки. Умножение
+и деление отбрасывают лишние знаки так же, как BigDecimal::with_scale(10),
+поэтому результат совпадает с прежним расчетом на BigDecimal. Все операции
+проверяемые: переполнение, деление на ноль и битые цены - это CalcError,
+а не паника.
+
+Price (котируемой валюты за единицу базовой) и Qty (количество базовой
+валюты пары) - отдельные типы поверх Fixed, чтобы цену нельзя было
+передать вместо количества. Суммы, доли и проценты - просто Fixed.
+*/
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::BigDecimal;
+use std::fmt;
+use std::str::FromStr;
+
+pub const SCALE: u32 = 10;
+const UNIT: i128 = 10i128.pow(SCALE);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i128);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Price(Fixed);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Qty(Fixed);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CalcError {
+    MissingPrice(String),                        // по символу еще нет цены
+    ZeroPrice(String),                           // нулевая цена, делить на нее нельзя
+    Malformed { symbol: String, value: String }, // цена не парсится как десятичное число
+    BelowMinNotional(String),                    // нога меньше минимальной сделки символа
+    DivByZero,
+    Overflow,
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcError::MissingPrice(symbol) => write!(f, "no price for {}", symbol),
+            CalcError::ZeroPrice(symbol) => write!(f, "zero price for {}", symbol),
+            CalcError::Malformed { symbol, value } => {
+                write!(f, "malformed price {:?} for {}", value, symbol)
+            }
+            CalcError::BelowMinNotional(symbol) => {
+                write!(f, "leg below min notional for {}", symbol)
+            }
+            CalcError::DivByZero => write!(f, "division by zero"),
+            CalcError::Overflow => write!(f, "fixed-point overflow"),
+        }
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(UNIT);
+
+    pub fn from_raw(raw: i128) -> Self {
+        Fixed(raw)
+    }
+
+    pub fn raw(self) -> i128 {
+        self.0
+    }
+
+    pub fn from_int(value: i64) -> Self {
+        Fixed(value as i128 * UNIT)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /*
+    Десятичная строка вида "-123.456". Знаки дальше десятого отбрасываются,
+    у биржевых цен и объемов их не бывает.
+    */
+    pub fn parse(s: &str) -> Option<Self> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+        if int_part.is_empty() && frac_part.is_empty() {
+            return None;
+        }
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if !all_digits(int_part) || !all_digits(frac_part) {
+            return None;
+        }
+
+        let mut raw: i128 = 0;
+        for b in int_part.bytes() {
+            raw = raw.checked_mul(10)?.checked_add((b - b'0') as i128)?;
+        }
+        raw = raw.checked_mul(UNIT)?;
+        let mut place = UNIT / 10;
+        for b in frac_part.bytes().take(SCALE as usize) {
+            raw += (b - b'0') as i128 * place;
+            place /= 10;
+        }
+        Some(Fixed(if negative { -raw } else { raw }))
+    }
+
+    pub fn checked_add(self, other: Fixed) -> Result<Fixed, CalcError> {
+        self.0
+            .checked_add(other.0)
+            .map(Fixed)
+            .ok_or(CalcError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Fixed) -> Result<Fixed, CalcError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Fixed)
+            .ok_or(CalcError::Overflow)
+    }
+
+    // произведение, лишние знаки отбрасываются
+    pub fn checked_mul(self, other: Fixed) -> Result<Fixed, CalcError> {
+        self.0
+            .checked_mul(other.0)
+            .map(|product| Fixed(product / UNIT))
+            .ok_or(CalcError::Overflow)
+    }
+
+    // частное, лишние знаки отбрасываются
+    pub fn checked_div(self, other: Fixed) -> Result<Fixed, CalcError> {
+        if other.is_zero() {
+            return Err(CalcError::DivByZero);
+        }
+        self.0
+            .checked_mul(UNIT)
+            .map(|scaled| Fixed(scaled / other.0))
+            .ok_or(CalcError::Overflow)
+    }
+
+    // отбросить знаки после decimals-го (как with_scale(decimals))
+    pub fn truncate(self, decimals: u32) -> Fixed {
+        if decimals >= SCALE {
+            return self;
+        }
+        let step = 10i128.pow(SCALE - decimals);
+        Fixed(self.0 / step * step)
+    }
+
+    // вниз до кратного шагу цены или лота биржи; нулевой шаг - без изменений
+    pub fn round_to_tick(self, tick: Fixed) -> Fixed {
+        if tick.0 <= 0 {
+            return self;
+        }
+        Fixed(self.0.div_euclid(tick.0) * tick.0)
+    }
+
+    pub fn to_bigdecimal(self) -> BigDecimal {
+        BigDecimal::new(BigInt::from(self.0), SCALE as i64)
+    }
+
+    pub fn from_bigdecimal(value: &BigDecimal) -> Option<Fixed> {
+        // to_string малые значения пишет с экспонентой (7.4E-7), parse ее не понимает
+        Fixed::parse(&value.with_scale(SCALE as i64).to_plain_string())
+    }
+}
+
+impl FromStr for Fixed {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Fixed::parse(s).ok_or(())
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let unit = UNIT as u128;
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            abs / unit,
+            abs % unit,
+            width = SCALE as usize
+        )
+    }
+}
+
+impl Price {
+    pub const ZERO: Price = Price(Fixed::ZERO);
+
+    pub fn new(value: Fixed) -> Self {
+        Price(value)
+    }
+
+    pub fn value(self) -> Fixed {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0.is_zero()
+    }
+
+    // вниз до шага цены
+    pub fn round_to_tick(self, tick: Price) -> Price {
+        Price(self.0.round_to_tick(tick.0))
+    }
+
+    // сколько базовой валюты покупается на amount котируемой
+    pub fn qty_for(self, amount: Fixed) -> Result<Qty, CalcError> {
+        amount.checked_div(self.0).map(Qty)
+    }
+}
+
+impl Qty {
+    pub const ZERO: Qty = Qty(Fixed::ZERO);
+
+    pub fn new(value: Fixed) -> Self {
+        Qty(value)
+    }
+
+    pub fn value(self) -> Fixed {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0.is_zero()
+    }
+
+    // вниз до шага лота
+    pub fn round_to_step(self, step: Qty) -> Qty {
+        Qty(self.0.round_to_tick(step.0))
+    }
+
+    // сумма сделки в котируемой валюте
+    pub fn notional(self, price: Price) -> Result<Fixed, CalcError> {
+        self.0.checked_mul(price.0)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Qty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+pub fn parse_price(symbol: &str, value: &str) -> Result<Price, CalcError> {
+    Fixed::parse(value)
+        .map(Price)
+        .ok_or_else(|| CalcError::Malformed {
+            symbol: symbol.to_string(),
+            value: value.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::FromPrimitive;
+    use proptest::prelude::*;
+
+    // цена биржи от 0.0001 до 100000, до 8 знаков после точки
+    fn price() -> impl Strategy<Value = String> {
+        (10_000u64..10_000_000_000_000)
+            .prop_map(|raw| format!("{}.{:08}", raw / 100_000_000, raw % 100_000_000))
+    }
+
+    proptest! {
+        // прежний расчет ног: (amount * bid).with_scale(10) и (amount / ask).with_scale(10)
+        #[test]
+        fn legs_match_bigdecimal_chain(
+            prices in prop::array::uniform3(price()),
+            sells in prop::array::uniform3(any::<bool>()),
+        ) {
+            let mut fixed = Fixed::ONE;
+            let mut decimal = BigDecimal::from(1);
+            for (text, sell) in prices.iter().zip(sells) {
+                let price = Fixed::parse(text).unwrap();
+                let expected = BigDecimal::from_str(text).unwrap();
+                if sell {
+                    fixed = fixed.checked_mul(price).unwrap();
+                    decimal = (decimal * expected).with_scale(SCALE as i64);
+                } else {
+                    fixed = fixed.checked_div(price).unwrap();
+                    decimal = (decimal / expected).with_scale(SCALE as i64);
+                }
+                prop_assert_eq!(fixed.to_bigdecimal(), decimal.clone());
+            }
+        }
+    }
+
+    #[test]
+    fn division_by_zero_is_its_own_error() {
+        assert_eq!(
+            Fixed::ONE.checked_div(Fixed::ZERO),
+            Err(CalcError::DivByZero)
+        );
+        assert_eq!(Price::ZERO.qty_for(Fixed::ONE), Err(CalcError::DivByZero));
+        let max = Fixed::from_raw(i128::MAX);
+        assert_eq!(max.checked_div(Fixed::ONE), Err(CalcError::Overflow));
+    }
+
+    #[test]
+    fn small_bigdecimal_converts_without_exponent() {
+        let fee = BigDecimal::from_f64(7.455640155772044e-7).unwrap();
+        assert_eq!(Fixed::from_bigdecimal(&fee), Fixed::parse("0.0000007455"));
+    }
+}
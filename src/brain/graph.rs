@@ -1,111 +1,431 @@
+/*
+Граф валют.
+
+Узел - валюта (Asset) с ролью: base - валюта, где лежат деньги и с которой
+начинается треугольник, alt - промежуточная. Каждая рабочая пара дает два
+ребра: base пары -> quote пары (SELL, по bid) и обратно (BUY, по ask).
+На ребре - живая цена ноги и ее комиссия. Поиск циклов, направления ног и
+оценка треугольника движком (calculate_triangle) читают все прямо с ребер.
+*/
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 
-// use petgraph::dot::{Config, Dot};
-use petgraph::graph::{DiGraph, NodeIndex};
-use regex::Regex;
+use petgraph::graph::{DiGraph, EdgeIndex, NodeIndex};
+use tracing::warn;
 
 use crate::brain_sets::{AltCurrency, BaseCurrency, ParsedPairs};
+use crate::symbols::SymbolRegistry;
 
-use super::triangle::TriangleElement;
-
-pub fn create_graph<'a>(
-    mut graph: DiGraph<(&'a str, &'a str), ()>,
-    base: &'a [BaseCurrency],
-    alt: &'a [AltCurrency],
-    clean: &'a [ParsedPairs],
-) -> DiGraph<(&'a str, &'a str), ()> {
-    let mut node_map = HashMap::new();
-
-    //сначала только базовые ноды сохраним, так как метки не переписываются это позволит монетам которые могут быть и base и alt быть только base
-    for base_currency in base {
-        for alt_currency in alt {
-            let concatenated = format!("{}{}", alt_currency.symbol, base_currency.symbol);
-            if let Some(_pair) = clean.iter().find(|pair| pair.symbol == concatenated) {
-                let _ = add_unique_node(
-                    &mut graph,
-                    &mut node_map,
-                    base_currency.symbol.as_str(),
-                    Some("base"),
-                );
-            }
+use super::fixed::{parse_price, CalcError, Fixed, Price};
+use super::observer::PriceUpdate;
+use super::triangle::{PairAssets, TriangleElement, TriangleKey};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetRole {
+    Base,
+    Alt,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Asset {
+    pub symbol: String,
+    pub role: AssetRole,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Side {
+    Buy,  // тратим котируемую валюту пары, получаем базовую
+    Sell, // продаем базовую валюту пары за котируемую
+}
+
+impl Side {
+    // так направление записано в TriangleKey::d и ногах треугольника
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Side::Buy => "BUY",
+            Side::Sell => "SELL",
         }
     }
-    //теперь еще раз, но сохраним все и еще свяжем ребрами (базовые метки не будут изменены)
-    for base_currency in base {
-        for alt_currency in alt {
-            let concatenated = format!("{}{}", alt_currency.symbol, base_currency.symbol);
-            if let Some(_pair) = clean.iter().find(|pair| pair.symbol == concatenated) {
-                let b = add_unique_node(
-                    &mut graph,
-                    &mut node_map,
-                    base_currency.symbol.as_str(),
-                    None,
-                );
-                let a = add_unique_node(
-                    &mut graph,
-                    &mut node_map,
-                    alt_currency.symbol.as_str(),
-                    Some("alt"),
-                );
-                graph.add_edge(b, a, ());
-                graph.add_edge(a, b, ());
-            }
-        }
+}
+
+#[derive(Clone, Debug)]
+pub struct PairEdge {
+    pub pair: PairAssets,
+    pub side: Side,
+    pub price: Result<Price, CalcError>, // SELL продает базовую валюту по bid, BUY покупает по ask
+    pub fee: Fixed,                      // доля, 0.001 = 0.1%
+}
+
+/*
+Пара для двух валют с явными base/quote. С правилами символов берется из них,
+иначе ищется символ first+second или second+first среди рабочих пар: такая
+пара помечается guessed. Если нашлись обе склейки, пара неоднозначна - None.
+*/
+pub fn resolve_pair(
+    first: &str,
+    second: &str,
+    clean: &[ParsedPairs],
+    symbols: Option<&SymbolRegistry>,
+) -> Option<PairAssets> {
+    let from_parts = |base: &str, quote: &str| PairAssets {
+        symbol: format!("{}{}", base, quote),
+        base: base.to_string(),
+        quote: quote.to_string(),
+        guessed: true,
+    };
+    if let Some(registry) = symbols {
+        return registry
+            .find(first, second)
+            .or_else(|| registry.find(second, first))
+            .filter(|info| clean.iter().any(|pair| pair.symbol == info.symbol))
+            .map(|info| PairAssets {
+                symbol: info.symbol.clone(),
+                base: info.base_asset.clone(),
+                quote: info.quote_asset.clone(),
+                guessed: false,
+            });
     }
+    let mut found = [from_parts(first, second), from_parts(second, first)]
+        .into_iter()
+        .filter(|candidate| clean.iter().any(|pair| pair.symbol == candidate.symbol));
+    match (found.next(), found.next()) {
+        (Some(pair), None) => Some(pair),
+        _ => None,
+    }
+}
 
-    graph
+/*
+Угаданные пары, чей символ склеивается из разных валют (BTCB+USD и BTC+BUSD
+дают BTCBUSD), отбрасываются все: по символу не понять, какие это валюты.
+*/
+fn drop_ambiguous(pairs: Vec<(usize, usize, PairAssets)>) -> Vec<(usize, usize, PairAssets)> {
+    let mut splits: HashMap<&str, HashSet<(&str, &str)>> = HashMap::new();
+    for (_, _, pair) in pairs.iter().filter(|(_, _, pair)| pair.guessed) {
+        splits
+            .entry(&pair.symbol)
+            .or_default()
+            .insert((&pair.base, &pair.quote));
+    }
+    let ambiguous: HashSet<String> = splits
+        .into_iter()
+        .filter(|(_, split)| split.len() > 1)
+        .map(|(symbol, split)| {
+            warn!(
+                symbol,
+                splits = ?split,
+                "pair symbol is ambiguous without exchangeInfo, skipped"
+            );
+            symbol.to_string()
+        })
+        .collect();
+    pairs
+        .into_iter()
+        .filter(|(_, _, pair)| !ambiguous.contains(&pair.symbol))
+        .collect()
 }
 
-fn add_unique_node<'a>(
-    graph: &mut DiGraph<(&'a str, &'a str), ()>,
-    node_map: &mut HashMap<&'a str, NodeIndex>,
-    value: &'a str,
-    label: Option<&'a str>,
-) -> NodeIndex {
-    if let Some(&node) = node_map.get(value) {
-        node
-    } else {
-        let label_str = label.unwrap_or("");
-        let node = graph.add_node((value, label_str));
-        node_map.insert(value, node);
-        node
+#[derive(Clone)]
+pub struct CurrencyGraph {
+    graph: DiGraph<Asset, PairEdge>,
+    nodes: HashMap<String, NodeIndex>,
+    pairs: HashMap<String, [EdgeIndex; 2]>, // символ пары - ее SELL и BUY ребра
+}
+
+impl CurrencyGraph {
+    pub fn build(
+        base: &[BaseCurrency],
+        alt: &[AltCurrency],
+        clean: &[ParsedPairs],
+        symbols: Option<&SymbolRegistry>,
+        fee: Fixed,
+    ) -> Self {
+        let mut currency_graph = CurrencyGraph {
+            graph: DiGraph::new(),
+            nodes: HashMap::new(),
+            pairs: HashMap::new(),
+        };
+
+        // пары alt-base с индексами валют в настройках
+        let resolved = drop_ambiguous(
+            base.iter()
+                .enumerate()
+                .flat_map(|(b, base_currency)| {
+                    alt.iter().enumerate().filter_map(move |(a, alt_currency)| {
+                        resolve_pair(&alt_currency.symbol, &base_currency.symbol, clean, symbols)
+                            .map(|pair| (b, a, pair))
+                    })
+                })
+                .collect(),
+        );
+
+        //сначала только базовые ноды сохраним, так как роли не переписываются это позволит монетам которые могут быть и base и alt быть только base
+        for (b, _, _) in &resolved {
+            currency_graph.add_unique_node(&base[*b].symbol, AssetRole::Base);
+        }
+        //теперь еще раз, но сохраним все и еще свяжем ребрами (базовые роли не будут изменены)
+        for (b, a, pair) in resolved {
+            currency_graph.add_unique_node(&base[b].symbol, AssetRole::Base);
+            currency_graph.add_unique_node(&alt[a].symbol, AssetRole::Alt);
+            currency_graph.add_pair(pair, fee);
+        }
+
+        currency_graph
+    }
+
+    fn add_unique_node(&mut self, symbol: &str, role: AssetRole) -> NodeIndex {
+        if let Some(&node) = self.nodes.get(symbol) {
+            node
+        } else {
+            let node = self.graph.add_node(Asset {
+                symbol: symbol.to_string(),
+                role,
+            });
+            self.nodes.insert(symbol.to_string(), node);
+            node
+        }
+    }
+
+    fn add_pair(&mut self, pair: PairAssets, fee: Fixed) {
+        if self.pairs.contains_key(&pair.symbol) {
+            return;
+        }
+        let (Some(&base), Some(&quote)) = (self.nodes.get(&pair.base), self.nodes.get(&pair.quote))
+        else {
+            return;
+        };
+        // между двумя валютами одна пара, иначе циклы через них задвоятся
+        if self.graph.find_edge(base, quote).is_some() {
+            return;
+        }
+        let edge = |side| PairEdge {
+            pair: pair.clone(),
+            side,
+            price: Err(CalcError::MissingPrice(pair.symbol.clone())),
+            fee,
+        };
+        let sell = self.graph.add_edge(base, quote, edge(Side::Sell));
+        let buy = self.graph.add_edge(quote, base, edge(Side::Buy));
+        self.pairs.insert(pair.symbol.clone(), [sell, buy]);
+    }
+
+    pub fn graph(&self) -> &DiGraph<Asset, PairEdge> {
+        &self.graph
+    }
+
+    pub fn node(&self, symbol: &str) -> Option<NodeIndex> {
+        self.nodes.get(symbol).copied()
+    }
+
+    pub fn edge(&self, from: &str, to: &str) -> Option<&PairEdge> {
+        let edge = self.graph.find_edge(self.node(from)?, self.node(to)?)?;
+        Some(&self.graph[edge])
+    }
+
+    pub fn assets(&self, role: AssetRole) -> Vec<&str> {
+        self.graph
+            .node_weights()
+            .filter(|asset| asset.role == role)
+            .map(|asset| asset.symbol.as_str())
+            .collect()
+    }
+
+    pub fn pair_symbols(&self) -> impl Iterator<Item = &str> {
+        self.pairs.keys().map(String::as_str)
+    }
+
+    // ребро ноги треугольника: символ пары и сторона, как в TriangleElement
+    pub fn leg(&self, symbol: &str, side: &str) -> Option<&PairEdge> {
+        let [sell, buy] = self.pairs.get(symbol)?;
+        let edge = if side == Side::Sell.as_str() {
+            sell
+        } else {
+            buy
+        };
+        Some(&self.graph[*edge])
+    }
+
+    // живые цены ребер пары; false - пары в графе нет или цена битая
+    pub fn update_prices(&mut self, update: &PriceUpdate) -> bool {
+        let Some(&[sell, buy]) = self.pairs.get(&update.symbol) else {
+            return false;
+        };
+        let bid = parse_price(&update.symbol, &update.bid);
+        let ask = parse_price(&update.symbol, &update.ask);
+        let parsed = bid.is_ok() && ask.is_ok();
+        self.graph[sell].price = bid;
+        self.graph[buy].price = ask;
+        parsed
+    }
+
+    // все простые циклы из depth валют через хотя бы одну базовую, каждый один раз
+    pub fn cycles(&self, depth: usize) -> Cycles<'_> {
+        Cycles::new(&self.graph, depth)
+    }
+
+    // ребра замкнутого пути path[0] -> path[1] -> ... -> path[0]
+    pub fn path_edges(&self, path: &[&str]) -> Option<Vec<&PairEdge>> {
+        let len = path.len();
+        (0..len)
+            .map(|i| self.edge(path[i], path[(i + 1) % len]))
+            .collect()
+    }
+
+    // ноги треугольника в формате движка: символ пары и сторона
+    pub fn legs(&self, path: &[&str]) -> Option<Vec<TriangleElement>> {
+        let edges = self.path_edges(path)?;
+        Some(
+            edges
+                .into_iter()
+                .map(|edge| (edge.pair.symbol.clone(), edge.side.as_str().to_string()))
+                .collect(),
+        )
     }
 }
 
-pub fn re_cycles<'a>(cycles: &Vec<Vec<&str>>, clean: &'a [ParsedPairs]) -> Vec<Vec<String>> {
-    let mut result = Vec::new();
+/*
+Перебор простых циклов длины depth без копирования путей.
 
-    for cycle in cycles {
-        let len = cycle.len();
-        let mut inner_result = Vec::new();
+Цикл выдается в канонической форме: начинается с узла с наименьшим индексом,
+а из двух направлений обхода берется то, где второй узел меньше последнего
+(ребра пар всегда парные, так что обратный цикл тоже есть). Поэтому каждый
+цикл встречается ровно один раз, и triangle_sorting/remove_duplicates не
+нужны. Порядок валют в цикле - порядок обхода, пары между соседями есть.
 
-        for i in 0..len {
-            let first = cycle[i];
-            let second = cycle[(i + 1) % len];
-            let concatenated = format!("{}{}", first, second);
-            let reversed = format!("{}{}", second, first);
+Соседи каждого узла хранятся отсортированными: обход сразу пропускает узлы
+меньше корня, а замыкание цикла - двоичный поиск, а не перебор всех пар
+базовой валюты. Кроме списков соседей память - один путь и стек позиций
+глубиной depth, независимо от числа найденных циклов.
+*/
+pub struct Cycles<'a> {
+    graph: &'a DiGraph<Asset, PairEdge>,
+    depth: usize,
+    adjacency: Vec<Vec<NodeIndex>>,
+    next_root: usize,
+    path: Vec<NodeIndex>,
+    on_path: Vec<bool>,
+    stack: Vec<usize>, // позиция в списке соседей узла path[i]
+}
 
-            if clean.iter().any(|pair| pair.symbol == concatenated) {
-                inner_result.push(concatenated);
-            } else if clean.iter().any(|pair| pair.symbol == reversed) {
-                inner_result.push(reversed);
-            }
+impl<'a> Cycles<'a> {
+    fn new(graph: &'a DiGraph<Asset, PairEdge>, depth: usize) -> Self {
+        let adjacency = graph
+            .node_indices()
+            .map(|node| {
+                let mut neighbors: Vec<NodeIndex> = graph.neighbors(node).collect();
+                neighbors.sort_unstable();
+                neighbors.dedup();
+                neighbors
+            })
+            .collect();
+        Cycles {
+            graph,
+            depth,
+            adjacency,
+            next_root: 0,
+            path: Vec::with_capacity(depth),
+            on_path: vec![false; graph.node_count()],
+            stack: Vec::with_capacity(depth),
+        }
+    }
+
+    fn push(&mut self, node: NodeIndex) {
+        // соседи меньше корня в цикл с этим корнем не входят
+        let root = self.path.first().copied().unwrap_or(node);
+        let start = self.adjacency[node.index()].partition_point(|&n| n <= root);
+        self.path.push(node);
+        self.on_path[node.index()] = true;
+        self.stack.push(start);
+    }
+
+    fn pop(&mut self) {
+        self.stack.pop();
+        if let Some(node) = self.path.pop() {
+            self.on_path[node.index()] = false;
         }
+    }
+
+    // последний узел замыкает цикл в каноническом направлении и через базовую валюту
+    fn closes(&self, last: NodeIndex) -> bool {
+        let root = self.path[0];
+        last > self.path[1]
+            && self.adjacency[last.index()].binary_search(&root).is_ok()
+            && (self.graph[last].role == AssetRole::Base
+                || self
+                    .path
+                    .iter()
+                    .any(|&node| self.graph[node].role == AssetRole::Base))
+    }
+}
+
+impl<'a> Iterator for Cycles<'a> {
+    type Item = Vec<&'a str>;
 
-        result.push(inner_result);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.depth < 3 {
+            return None;
+        }
+        loop {
+            let Some(&position) = self.stack.last() else {
+                // следующий корень: все циклы через меньшие узлы уже выданы
+                if self.next_root >= self.graph.node_count() {
+                    return None;
+                }
+                let root = NodeIndex::new(self.next_root);
+                self.next_root += 1;
+                self.push(root);
+                continue;
+            };
+            let current = self.path[self.path.len() - 1];
+            let Some(&next) = self.adjacency[current.index()].get(position) else {
+                self.pop();
+                continue;
+            };
+            *self.stack.last_mut().unwrap() += 1;
+            if self.on_path[next.index()] {
+                continue;
+            }
+            if self.path.len() + 1 == self.depth {
+                // последний узел цикла: не углубляемся, только проверяем замыкание
+                if self.closes(next) {
+                    let graph = self.graph;
+                    let mut cycle: Vec<&str> = self
+                        .path
+                        .iter()
+                        .map(|&node| graph[node].symbol.as_str())
+                        .collect();
+                    cycle.push(graph[next].symbol.as_str());
+                    return Some(cycle);
+                }
+                continue;
+            }
+            self.push(next);
+        }
     }
+}
 
-    result
+pub fn re_cycles(graph: &CurrencyGraph, cycles: &Vec<Vec<&str>>) -> Vec<Vec<PairAssets>> {
+    cycles
+        .iter()
+        .map(|cycle| {
+            let len = cycle.len();
+            (0..len)
+                .filter_map(|i| graph.edge(cycle[i], cycle[(i + 1) % len]))
+                .map(|edge| edge.pair.clone())
+                .collect()
+        })
+        .collect()
 }
 
 pub fn find_differences(
     clean_pairs: &[ParsedPairs],
-    need_cycles: &Vec<Vec<String>>,
+    need_cycles: &[Vec<PairAssets>],
 ) -> Vec<Vec<String>> {
     let need_set: HashSet<&str> = need_cycles
         .iter()
         .flat_map(|cycle| cycle.iter())
-        .map(|s| s.as_str())
+        .map(|pair| pair.symbol.as_str())
         .collect();
     let mut remaining_pairs: Vec<ParsedPairs> = clean_pairs.to_vec();
     remaining_pairs.retain(|pair| !need_set.contains(pair.symbol.as_str()));
@@ -116,7 +436,7 @@ pub fn find_differences(
     differences
 }
 
-pub fn clearing(clean_pairs: &[ParsedPairs], diff: &Vec<Vec<String>>) -> Vec<String> {
+pub fn clearing(clean_pairs: &[ParsedPairs], diff: &[Vec<String>]) -> Vec<String> {
     let diff_set: HashSet<&str> = diff
         .iter()
         .flat_map(|cycle| cycle.iter())
@@ -131,123 +451,48 @@ pub fn clearing(clean_pairs: &[ParsedPairs], diff: &Vec<Vec<String>>) -> Vec<Str
     clearing
 }
 /*
-https://viz-js.com
+Обе стороны обхода цикла (A -> B -> C -> A и A -> C -> B -> A) от каждой
+базовой валюты цикла, в порядке base. Путь повернут так, чтобы начинаться
+со своей стартовой валюты: деньги, лежащие в любой из базовых валют цикла,
+могут пройти по нему. Цикл без базовой валюты не торгуется.
 */
+pub fn orientations<'a>(cycle: &[&'a str], base: &[BaseCurrency]) -> Vec<Vec<&'a str>> {
+    let mut paths = Vec::new();
+    for start in base
+        .iter()
+        .filter_map(|b| cycle.iter().position(|asset| *asset == b.symbol))
+    {
+        let forward: Vec<&str> = cycle[start..]
+            .iter()
+            .chain(&cycle[..start])
+            .copied()
+            .collect();
+        let mut backward = vec![forward[0]];
+        backward.extend(forward[1..].iter().rev());
+        paths.push(forward);
+        paths.push(backward);
+    }
+    paths
+}
 
+/*
+Треугольники для движка: каждый цикл из трех валют в обеих сторонах обхода
+от каждой своей базовой валюты. Ключ - пары в порядке сделок и стартовая
+валюта, сторона каждой ноги лежит в самих ногах и берется с ребер графа.
+*/
 pub fn create_triangles(
-    cycles: &Vec<String>,
-    base: &Vec<BaseCurrency>,
-    mut current: String,
-    mut direction: String,
-    mut accumulator: Vec<TriangleElement>,
-) -> (Vec<TriangleElement>, String) {
-    if cycles.is_empty() {
-        return (accumulator, current);
-    }
-    if current.is_empty() {
-        if direction == "SELL" {
-            for base_curr in base {
-                let re = Regex::new(&format!(r"^{}", base_curr.symbol)).unwrap(); //начало строки
-                for element in cycles.iter() {
-                    if re.is_match(element) {
-                        current = base_curr.symbol.clone();
-                        break;
-                    }
-                }
-                if !current.is_empty() {
-                    break;
-                }
-            }
-        } else if direction == "BUY" {
-            for base_curr in base {
-                let re = Regex::new(&format!(r"{}$", base_curr.symbol)).unwrap(); //конец строки
-                for element in cycles.iter() {
-                    if re.is_match(element) {
-                        current = base_curr.symbol.clone();
-                        break;
-                    }
-                }
-                if !current.is_empty() {
-                    break;
+    graph: &CurrencyGraph,
+    base: &[BaseCurrency],
+) -> HashMap<TriangleKey, Vec<TriangleElement>> {
+    let mut triangles = HashMap::new();
+    for cycle in graph.cycles(3) {
+        for path in orientations(&cycle, base) {
+            if let Some(legs) = graph.legs(&path) {
+                if let Some(key) = TriangleKey::from_legs(&legs, path[0]) {
+                    triangles.insert(key, legs);
                 }
             }
         }
     }
-    //про current - выше он установлен в одну из базовых валют потому что там деньги и начинаем с них
-    //ниже нужно будет обновить
-
-    // Найти первый элемент, соответствующий текущему значению по регулярному выражению
-    let mut first_element = None;
-    let mut first_index = None;
-
-    for (index, element) in cycles.iter().enumerate() {
-        let re_start = Regex::new(&format!(r"^{}", current)).unwrap(); // начало строки
-        let re_end = Regex::new(&format!(r"{}$", current)).unwrap(); // конец строки
-
-        if direction == "BUY" && re_end.is_match(element) {
-            first_element = Some(element.clone());
-            first_index = Some(index);
-            break;
-        } else if direction == "SELL" && re_start.is_match(element) {
-            first_element = Some(element.clone());
-            first_index = Some(index);
-            break;
-        } else {
-        }
-    }
-
-    if first_element.is_none() {
-        return (accumulator, current);
-    }
-    let first_element = first_element.unwrap();
-
-    // новый вектор без найденного элемента
-    let mut new_cycles = cycles.clone();
-    if let Some(index) = first_index {
-        new_cycles.remove(index);
-    }
-
-    /*
-     выше - отобрали первую пару (first_element) согласно направлению и текущей валюты (current)
-     правило - один символ встречается только в двух парах.
-     Значит если один раз его нашли и исключили то он остался только в одной уже паре
-    */
-    let mut re_end = Regex::new(&format!(r"^{}", current)).unwrap(); //начало строки
-    let mut re_start = Regex::new(&format!(r"{}$", current)).unwrap(); //конец строки
-
-    /*
-     нужно сменить текущую валюту (current) на вторую половину пары где был прошлый current
-    */
-    if re_start.is_match(&first_element) {
-        current = first_element[..first_element.len() - current.len()].to_string();
-    } else if re_end.is_match(&first_element) {
-        current = first_element[current.len()..].to_string();
-    }
-
-    re_end = Regex::new(&format!(r"^{}", current)).unwrap(); //начало строки
-    re_start = Regex::new(&format!(r"{}$", current)).unwrap(); //конец строки
-
-    // Создаем новый элемент TriangleElement
-    let modified_element = (first_element, direction.clone());
-    // Добавляем его в аккумулятор
-    accumulator.push(modified_element);
-    /*
-    сменить направление если нужно
-     */
-    if new_cycles.len() == 0 {
-        return (accumulator, current);
-    }
-
-    for nc in &new_cycles {
-        if re_end.is_match(&nc) {
-            direction = "SELL".to_string();
-            break;
-        } else if re_start.is_match(&nc) {
-            direction = "BUY".to_string();
-            break;
-        } else {
-        }
-    }
-    // Рекурсивный вызов с обновленным current/direction
-    create_triangles(&new_cycles, base, current, direction.clone(), accumulator)
+    triangles
 }
@@ -0,0 +1,117 @@
+/*
+Рынок из файла настроек: рабочие пары, граф валют, циклы и треугольники.
+
+Рабочие пары берутся из правил символов (exchangeInfo), если они заданы:
+все торгуемые символы между валютами из настроек. Без правил считается, что
+каждая alt торгуется к каждой base символом ALTBASE: такие пары в графе
+помечены guessed, а символы, которые склеиваются из разных валют, отброшены.
+Подписываться нужно только на pairs - пары, которые входят хотя бы в один цикл.
+*/
+use bigdecimal::{BigDecimal, FromPrimitive};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::brain_sets::{
+    read_setting_alt_currency, read_setting_base_currency, AltCurrency, BaseCurrency, ParsedPairs,
+    Template,
+};
+use crate::error::Result;
+use crate::symbols::SymbolRegistry;
+
+use super::fixed::Fixed;
+use super::graph::{clearing, create_triangles, find_differences, re_cycles, CurrencyGraph};
+use super::triangle::{TriangleElement, TriangleKey};
+
+pub struct Market {
+    pub base: Vec<BaseCurrency>,
+    pub alt: Vec<AltCurrency>,
+    pub graph: CurrencyGraph,
+    pub cycles: Vec<Vec<String>>, // канонические циклы из трех валют
+    pub pairs: Vec<String>,       // символы для подписки
+    pub triangles: HashMap<TriangleKey, Vec<TriangleElement>>,
+}
+
+impl Market {
+    pub fn load<P: AsRef<Path>>(
+        settings: P,
+        symbols: Option<&SymbolRegistry>,
+        fee_rate: f64,
+    ) -> Result<Self> {
+        let base = read_setting_base_currency(&settings)?;
+        let alt = read_setting_alt_currency(&settings)?;
+        Ok(Market::new(base, alt, symbols, fee_rate))
+    }
+
+    pub fn new(
+        base: Vec<BaseCurrency>,
+        alt: Vec<AltCurrency>,
+        symbols: Option<&SymbolRegistry>,
+        fee_rate: f64,
+    ) -> Self {
+        let clean = candidate_pairs(&base, &alt, symbols);
+        let fee = BigDecimal::from_f64(fee_rate)
+            .and_then(|fee| Fixed::from_bigdecimal(&fee))
+            .unwrap_or(Fixed::ZERO);
+        let graph = CurrencyGraph::build(&base, &alt, &clean, symbols, fee);
+
+        let cycles: Vec<Vec<&str>> = graph.cycles(3).collect();
+        let need = re_cycles(&graph, &cycles);
+        let pairs = clearing(&clean, &find_differences(&clean, &need));
+        let cycles = cycles
+            .into_iter()
+            .map(|cycle| cycle.into_iter().map(str::to_string).collect())
+            .collect();
+        let triangles = create_triangles(&graph, &base);
+
+        Market {
+            base,
+            alt,
+            graph,
+            cycles,
+            pairs,
+            triangles,
+        }
+    }
+}
+
+// кандидаты в рабочие пары; лишние отсеет граф, в подписку попадут только пары циклов
+fn candidate_pairs(
+    base: &[BaseCurrency],
+    alt: &[AltCurrency],
+    symbols: Option<&SymbolRegistry>,
+) -> Vec<ParsedPairs> {
+    let pair = |symbol: String| {
+        let template = Template { ixs: 0, ixe: 0 };
+        ParsedPairs::new(symbol, template.clone(), template.clone(), template)
+    };
+    match symbols {
+        Some(registry) => {
+            let assets: HashSet<&str> = base
+                .iter()
+                .map(|b| b.symbol.as_str())
+                .chain(alt.iter().map(|a| a.symbol.as_str()))
+                .collect();
+            let mut found: Vec<&str> = registry
+                .iter()
+                .filter(|info| {
+                    assets.contains(info.base_asset.as_str())
+                        && assets.contains(info.quote_asset.as_str())
+                })
+                .map(|info| info.symbol.as_str())
+                .collect();
+            found.sort_unstable();
+            found
+                .into_iter()
+                .map(|symbol| pair(symbol.to_string()))
+                .collect()
+        }
+        None => base
+            .iter()
+            .flat_map(|b| {
+                alt.iter()
+                    .filter(move |a| a.symbol != b.symbol)
+                    .map(move |a| pair(format!("{}{}", a.symbol, b.symbol)))
+            })
+            .collect(),
+    }
+}
@@ -1,32 +1,44 @@
+pub mod allocation;
+pub mod export;
+pub mod fixed;
 pub mod graph;
+pub mod market;
 pub mod observer;
+pub mod signal;
+pub mod status;
+pub mod tracker;
 pub mod triangle;
-use crate::brain::observer::Observable;
-use crate::uds_write::{uds_connect, uds_write_to};
+pub mod warmup;
+use crate::brain::allocation::Allocator;
+use crate::brain::fixed::{CalcError, Fixed, Qty};
+use crate::brain::graph::{Asset, AssetRole, CurrencyGraph, Side};
+use crate::brain::market::Market;
+use crate::brain::observer::{Observable, ObserverHandle, PriceUpdate};
+use crate::brain::signal::{Signal, SignalSink};
+use crate::brain::tracker::{OpportunityEvent, OpportunityTracker, TrackerConfig};
+use crate::brain::warmup::{Warmup, WarmupPolicy};
+use crate::error::Error;
+use crate::latency;
+use crate::symbols::SymbolRegistry;
 use bigdecimal::{BigDecimal, FromPrimitive};
-use chrono::Local;
 use crossbeam::queue::SegQueue;
 use dashmap::DashMap;
 use petgraph::graph::DiGraph;
 use rand::Rng;
-use std::fmt::Formatter;
-use std::str::FromStr;
-use std::sync::atomic::AtomicU64;
-use std::sync::Arc;
+use std::iter;
+use std::sync::atomic::{AtomicI64, AtomicU64};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use std::{
     collections::{HashMap, HashSet, VecDeque},
-    sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
-        Mutex,
-    },
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
-use std::{iter, task};
-use tokio::net::UnixStream;
+use tracing::{debug, debug_span};
 use triangle::TriangleKey;
 
 #[derive(Clone, Debug)]
 struct DataStorage {
-    map: DashMap<String, (String, String)>, // ключ - symbol, значение - (price, volume)
+    map: DashMap<String, PriceUpdate>, // ключ - symbol, значение - последнее обновление
 }
 
 impl DataStorage {
@@ -36,13 +48,13 @@ impl DataStorage {
         }
     }
 
-    fn insert(&self, symbol: String, price: String, volume: String) {
-        // Вставляем символ с данными о цене и объеме
-        self.map.insert(symbol, (price, volume));
+    fn insert(&self, update: &PriceUpdate) {
+        // Вставляем символ с последними ценами и объемами
+        self.map.insert(update.symbol.clone(), update.clone());
     }
 
     fn count(&self) -> usize {
-        return self.map.len();
+        self.map.len()
     }
 
     // fn display(&self) {
@@ -62,6 +74,64 @@ lazy_static::lazy_static! {
     static ref SRT: SymbolRefTriangles = DashMap::new();
     static ref RATE: AtomicU64 = AtomicU64::new(1.0f64.to_bits());
     static ref EARN_QUEUE: Arc<SegQueue<EarnSortedData>> = Arc::new(SegQueue::new());
+    static ref PROCESSED: AtomicU64 = AtomicU64::new(0);
+    static ref TRACKER: Mutex<OpportunityTracker> =
+        Mutex::new(OpportunityTracker::new(TrackerConfig::default()));
+    static ref MAX_AGE_US: AtomicI64 = AtomicI64::new(0); // 0 - возраст цен не проверяется
+    static ref STALE_SKIPS: AtomicU64 = AtomicU64::new(0);
+    static ref WARMUP: Mutex<Warmup> = Mutex::new(Warmup::new(WarmupPolicy {
+        min_coverage: 100.0,
+        timeout: None,
+    }));
+    static ref TRIANGLE_KEYS: RwLock<Vec<TriangleKey>> = RwLock::new(Vec::new());
+    static ref EXPECTED_SYMBOLS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+    // граф рынка с живыми ценами на ребрах: по нему считает движок, его же отдает /graph
+    static ref LIVE_GRAPH: RwLock<Option<CurrencyGraph>> = RwLock::new(None);
+}
+
+// Параметры движка, собираются из Config::brain_config
+#[derive(Clone, Debug)]
+pub struct BrainConfig {
+    pub rate: f64,                       // минимальный earn для сигнала, %
+    pub signal_min_change: f64,          // существенное изменение net_earn возможности, п.п.
+    pub signal_cooldown: Duration,       // минимальный интервал между сигналами по треугольнику
+    pub signal_top_k: usize,             // сколько лучших треугольников отправлять за тик
+    pub signal_exclusive_pairs: bool,    // не брать треугольники с парой, уже занятой выше в топе
+    pub capital: f64,                    // общий капитал, делится по BaseCurrency::percentage
+    pub capital_asset: String,           // в какой валюте указан capital
+    pub max_price_age: Option<Duration>, // цена старше - нога устарела, треугольник не считаем
+    pub warmup: WarmupPolicy,
+    pub calc_scale: u32, // знаков после каждой ноги, не больше fixed::SCALE
+    pub earn_scale: u32, // знаков в отдаваемых earn и bps
+    pub fee_rate: f64,   // комиссия за ногу, доля (0.001 = 0.1%)
+    pub symbols: Option<Arc<SymbolRegistry>>, // шаги лота и min notional символов
+}
+
+// неизменяемые после инициализации параметры, с которыми работает наблюдатель
+struct EngineParams {
+    rate: Fixed, // общий порог earn, %
+    config: BrainConfig,
+    allocator: Allocator,
+}
+
+impl EngineParams {
+    fn new(config: &BrainConfig, market: &Market, rate: Fixed) -> Self {
+        EngineParams {
+            rate,
+            config: config.clone(),
+            allocator: Allocator::new(
+                &market.base,
+                config.capital,
+                &config.capital_asset,
+                &market.graph,
+            ),
+        }
+    }
+}
+
+// сколько обновлений цен движок уже обработал (включая период наполнения)
+pub fn processed_updates() -> u64 {
+    PROCESSED.load(Ordering::SeqCst)
 }
 
 #[derive(Debug)]
@@ -69,11 +139,18 @@ struct EarnSortedData {
     triangle_key: TriangleKey,
     final_amount: BigDecimal,
     earn: BigDecimal,
+    net_earn: BigDecimal, // по нему, как и по порогу, ранжируется топ
+    gross_bps: BigDecimal,
+    net_bps: BigDecimal,
+    event: OpportunityEvent,
+    started_ts: i64,
+    order_size: Option<BigDecimal>,
+    profit: Option<BigDecimal>,
 }
 
 impl PartialEq for EarnSortedData {
     fn eq(&self, other: &Self) -> bool {
-        self.earn == other.earn
+        self.net_earn == other.net_earn
     }
 }
 
@@ -81,13 +158,13 @@ impl Eq for EarnSortedData {}
 
 impl PartialOrd for EarnSortedData {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        other.earn.partial_cmp(&self.earn) // Сортировка по убыванию earn
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for EarnSortedData {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+        other.net_earn.cmp(&self.net_earn) // Сортировка по убыванию net_earn
     }
 }
 
@@ -109,16 +186,27 @@ fn build_immutable_storage(
     }
 }
 
+// наблюдатель движка; handle нужен, чтобы остановить его (replay дожидается так конца расчета)
 pub fn initialize_observers(
-    observable: Arc<Mutex<Observable>>,
-    count: usize,
-    triangles: &HashMap<TriangleKey, Vec<(String, String)>>,
-    rate: f64,
-) {
-    COUNT.store(count, Ordering::SeqCst);
-    RATE.store(rate.to_bits(), Ordering::SeqCst);
+    observable: &Observable,
+    market: &Market,
+    config: &BrainConfig,
+    sink: Arc<dyn SignalSink>,
+) -> ObserverHandle {
+    let triangles = &market.triangles;
+    COUNT.store(market.pairs.len(), Ordering::SeqCst);
+    RATE.store(config.rate.to_bits(), Ordering::SeqCst);
+    let max_age_us = config.max_price_age.map_or(0, |age| age.as_micros() as i64);
+    MAX_AGE_US.store(max_age_us, Ordering::SeqCst);
+    *WARMUP.lock().unwrap() = Warmup::new(config.warmup.clone());
+    REGULAR_MODE.store(false, Ordering::SeqCst);
+    *TRACKER.lock().unwrap() = OpportunityTracker::new(TrackerConfig {
+        min_change: BigDecimal::from_f64(config.signal_min_change).unwrap_or_default(),
+        cooldown: config.signal_cooldown,
+    });
     let _rate = f64::from_bits(RATE.load(Ordering::SeqCst));
     let rate_bd = BigDecimal::from_f64(_rate).unwrap_or(BigDecimal::from(100));
+    let rate = Fixed::from_bigdecimal(&rate_bd).unwrap_or(Fixed::from_int(100));
 
     let mut unique_symbols: HashSet<String> = HashSet::new();
     for key in triangles.keys() {
@@ -127,57 +215,43 @@ pub fn initialize_observers(
         unique_symbols.insert(key.c.clone());
     }
 
-    build_immutable_storage(&triangles, &unique_symbols);
-
-    // Создаем `UnixStream` внутри функции
-    let socket_path = "/tmp/arm_arbitr_socket";
-    let runtime = Arc::new(tokio::runtime::Runtime::new().unwrap()); // Оборачиваем в Arc
-
-    let uid = generate_random_id(4);
-    // Получаем поток асинхронно
-    let runtime_clone = Arc::clone(&runtime);
-    runtime.spawn(async move {
-        match uds_connect(socket_path).await {
-            Ok(stream) => {
-                let stream = Arc::new(Mutex::new(stream)); // Оборачиваем в Arc<Mutex<UnixStream>>
-
-                let stream_clone = Arc::clone(&stream);
-                observable
-                    .lock()
-                    .unwrap()
-                    .add_observer(Box::new(move |symbol, price, volume| {
-                        PRICE_STORAGE.insert(symbol.clone(), price.clone(), volume.clone());
-                        if !REGULAR_MODE.load(Ordering::SeqCst) {
-                            let dsc = PRICE_STORAGE.count();
-                            let c = COUNT.load(Ordering::SeqCst);
-                            if dsc < c {
-                                println!("наполнение осталось {}", c - dsc);
-                                return;
-                            } else {
-                                REGULAR_MODE.store(true, Ordering::SeqCst);
-                                println!("*** начало работы ***");
-                            }
-                        } else {
-                            // Получаем доступ к потоку через Mutex
-                            let mut stream = stream_clone.lock().unwrap();
-                            react_to_update(symbol, &rate_bd, &mut *stream, &runtime_clone, &uid);
-                        }
-                    }));
-            }
-            Err(e) => {
-                eprintln!("Ошибка подключения: {:?}", e);
-            }
+    build_immutable_storage(triangles, &unique_symbols);
+    *TRIANGLE_KEYS.write().unwrap() = triangles.keys().cloned().collect();
+    let mut expected: Vec<String> = unique_symbols.into_iter().collect();
+    expected.sort();
+    *EXPECTED_SYMBOLS.write().unwrap() = expected;
+
+    *LIVE_GRAPH.write().unwrap() = Some(market.graph.clone());
+
+    let params = EngineParams::new(config, market, rate);
+    // sink не должен блокироваться: UdsSignalSink лишь кладет сообщение в канал UdsWriter
+    observable.add_observer(Box::new(move |update| {
+        PRICE_STORAGE.insert(update);
+        if let Some(graph) = LIVE_GRAPH.write().unwrap().as_mut() {
+            graph.update_prices(update);
         }
-    });
+        if !REGULAR_MODE.load(Ordering::SeqCst) {
+            let dsc = PRICE_STORAGE.count();
+            let c = COUNT.load(Ordering::SeqCst);
+            let done = WARMUP
+                .lock()
+                .unwrap()
+                .on_update(update.recv_ts, dsc, c, missing_symbols);
+            REGULAR_MODE.store(done, Ordering::SeqCst);
+        }
+        if REGULAR_MODE.load(Ordering::SeqCst) {
+            react_to_update(update, &params, sink.as_ref());
+        }
+        PROCESSED.fetch_add(1, Ordering::SeqCst);
+    }))
 }
 
-fn react_to_update(
-    symbol: &String,
-    rate: &BigDecimal,
-    stream: &mut UnixStream,
-    runtime: &tokio::runtime::Runtime,
-    uid: &String,
-) {
+fn react_to_update(update: &PriceUpdate, params: &EngineParams, sink: &dyn SignalSink) {
+    // время обработки тика видно с log.span_timing и фильтром ws::brain=debug
+    let _span = debug_span!("react_to_update", symbol = %update.symbol).entered();
+    let mut trace = update.trace;
+    trace.observed = latency::now_us();
+    let symbol = &update.symbol;
     if let Some(triangles) = SRT.get(symbol) {
         let mut unique_symbols: HashSet<&String> = HashSet::new();
 
@@ -186,124 +260,291 @@ fn react_to_update(
             unique_symbols.insert(&triangle_key.b);
             unique_symbols.insert(&triangle_key.c);
         }
-        let mut symbol_price_map: HashMap<String, (String, String)> = HashMap::new();
+        // объем считается по тому же снимку, что и ноги
+        unique_symbols.extend(params.allocator.conversion_pairs());
+        let mut symbol_price_map: HashMap<String, PriceUpdate> = HashMap::new();
         for symbol in unique_symbols {
-            if let Some(price_volume) = PRICE_STORAGE.map.get(symbol) {
-                symbol_price_map.insert(symbol.clone(), price_volume.value().clone());
-            } else {
-                println!("No data found for symbol: {}", symbol);
+            if let Some(update) = PRICE_STORAGE.map.get(symbol) {
+                symbol_price_map.insert(symbol.clone(), update.clone());
             }
         }
-
+        // цены на ребрах пишет только этот поток, до следующего тика они не меняются
+        let live = LIVE_GRAPH.read().unwrap();
+        let Some(graph) = live.as_ref() else {
+            return;
+        };
+
+        // трекер видит каждый треугольник тика: и открытия, и закрытия
+        let mut tracker = TRACKER.lock().unwrap();
         for (triangle_key, triangle) in triangles.iter() {
-            if let Some((final_amount, earn)) =
-                calculate_triangle(&symbol_price_map, triangle_key, triangle)
+            // треугольник готов, когда у всех ног есть цена; до этого трекер его не видит
+            if !triangle
+                .iter()
+                .all(|(pair, _)| symbol_price_map.contains_key(pair))
             {
-                if earn >= *rate {
+                continue;
+            }
+            // треугольник с устаревшей ногой не считаем, для трекера это закрытие
+            let evaluation = if has_stale_leg(&symbol_price_map, triangle, update.recv_ts) {
+                STALE_SKIPS.fetch_add(1, Ordering::Relaxed);
+                None
+            } else {
+                // порог сравнивается с доходностью за вычетом комиссий, до округления
+                let rate = params.allocator.threshold(triangle_key, params.rate);
+                // с правилами символов треугольник прогоняется на реальном объеме сделки
+                let start = params
+                    .config
+                    .symbols
+                    .as_ref()
+                    .and_then(|registry| {
+                        params.allocator.trial_size(
+                            &symbol_price_map,
+                            triangle_key,
+                            triangle,
+                            registry,
+                        )
+                    })
+                    .and_then(|size| Fixed::from_bigdecimal(&size))
+                    .filter(|size| !size.is_zero());
+                match calculate_triangle(graph, triangle, start, &params.config) {
+                    Ok(evaluation) => (evaluation.net_earn >= rate).then_some(evaluation),
+                    Err(err) => {
+                        debug!("{}: {}", triangle_key, err);
+                        None
+                    }
+                }
+            };
+            let result = evaluation.as_ref().map(|e| {
+                (
+                    e.final_amount.to_bigdecimal(),
+                    e.earn.to_bigdecimal(),
+                    e.net_earn.to_bigdecimal(),
+                )
+            });
+            match tracker.observe(triangle_key, result, update.recv_ts) {
+                Some(tracked) if tracked.event == OpportunityEvent::Close => {
+                    sink.closed(&tracked.opportunity);
+                }
+                Some(tracked) if tracked.candidate => {
+                    // кандидат бывает только при найденной возможности, значит evaluation есть
+                    let Some(evaluation) = evaluation else {
+                        continue;
+                    };
+                    let order_size =
+                        params
+                            .allocator
+                            .order_size(&symbol_price_map, triangle_key, triangle);
+                    // прибыль в стартовой валюте за вычетом комиссий
+                    let profit = order_size.as_ref().map(|size| {
+                        (size * evaluation.net_earn.to_bigdecimal() / BigDecimal::from(100))
+                            .with_scale(params.config.calc_scale as i64)
+                    });
                     // очередь
                     EARN_QUEUE.push(EarnSortedData {
-                        triangle_key: (*triangle_key).clone(),
-                        final_amount: final_amount,
-                        earn: earn,
+                        triangle_key: tracked.opportunity.key,
+                        final_amount: tracked.opportunity.final_amount,
+                        earn: tracked.opportunity.earn,
+                        net_earn: tracked.opportunity.net_earn,
+                        gross_bps: evaluation.gross_bps.to_bigdecimal(),
+                        net_bps: evaluation.net_bps.to_bigdecimal(),
+                        event: tracked.event,
+                        started_ts: tracked.opportunity.started_ts,
+                        order_size,
+                        profit,
                     });
                 }
+                _ => {}
             }
         }
+        drop(tracker);
+        drop(live);
         //для сортировки
         let mut data_vec: Vec<EarnSortedData> = Vec::new();
         while let Some(data) = EARN_QUEUE.pop() {
             data_vec.push(data);
         }
-        // Сортировка по earn
-        data_vec.sort_by(|a, b| b.earn.partial_cmp(&a.earn).unwrap());
-
-        if let Some(maxdata) = data_vec.first() {
-            let current_time = Local::now();
-            let formatted_time = current_time.format("%H:%M:%S%.6f");
-
-            let msg_to_arm = format!(
-                "{}  {} MAX -> {:?}, Final Amount: {}, Earn: {}\n",
-                uid,
-                formatted_time,
-                maxdata.triangle_key,
-                maxdata.final_amount.with_scale(6),
-                maxdata.earn
-            );
-
-            println!("{}", msg_to_arm);
-
-            // Используем Tokio runtime для асинхронной функции в синхронном коде
-            runtime.block_on(uds_write_to(stream, &msg_to_arm));
+        // Сортировка по net_earn, по убыванию (Ord у EarnSortedData)
+        data_vec.sort();
+        trace.calculated = latency::now_us();
+
+        let selected = select_top(&data_vec, &params.config);
+        // ушедшими считаются только отобранные, остальные кандидаты повторятся следующим тиком
+        let mut tracker = TRACKER.lock().unwrap();
+        for data in &selected {
+            tracker.mark_emitted(&data.triangle_key, update.recv_ts);
+        }
+        drop(tracker);
+
+        let batch: Vec<Signal> = selected
+            .into_iter()
+            .map(|data| Signal {
+                triangle_key: data.triangle_key.clone(),
+                final_amount: data.final_amount.clone(),
+                earn: data.earn.clone(),
+                gross_bps: data.gross_bps.clone(),
+                net_bps: data.net_bps.clone(),
+                event: data.event,
+                started_ts: data.started_ts,
+                order_size: data.order_size.clone(),
+                profit: data.profit.clone(),
+                ts: update.recv_ts,
+                trace,
+            })
+            .collect();
+        if !batch.is_empty() {
+            sink.emit(&batch);
         }
         //очистка
-        while let Some(_) = data_vec.pop() {}
-        while let Some(_) = EARN_QUEUE.pop() {}
+        data_vec.clear();
+        while EARN_QUEUE.pop().is_some() {}
     }
 }
 
-fn round_to_scale(value: BigDecimal, scale: i64) -> BigDecimal {
-    value.with_scale(scale)
+/*
+Первые signal_top_k из отсортированных по убыванию net_earn. С signal_exclusive_pairs
+треугольник пропускается, если делит пару с уже отобранным: исполнитель не
+сможет взять обе ноги одновременно.
+*/
+fn select_top<'a>(sorted: &'a [EarnSortedData], config: &BrainConfig) -> Vec<&'a EarnSortedData> {
+    let mut selected: Vec<&EarnSortedData> = Vec::new();
+    let mut used_pairs: HashSet<&str> = HashSet::new();
+
+    for data in sorted {
+        if selected.len() >= config.signal_top_k {
+            break;
+        }
+        let key = &data.triangle_key;
+        let pairs = [key.a.as_str(), key.b.as_str(), key.c.as_str()];
+        if config.signal_exclusive_pairs && pairs.iter().any(|p| used_pairs.contains(p)) {
+            continue;
+        }
+        used_pairs.extend(pairs);
+        selected.push(data);
+    }
+
+    selected
 }
 
-fn calculate_triangle(
-    spm: &HashMap<String, (String, String)>,
-    key: &TriangleKey,
-    t: &Vec<(String, String)>,
-) -> Option<(BigDecimal, BigDecimal)> {
-    // Извлекаем направления сделок из ключа
-    let (pair1, dir1) = (&t[0].0, &key.d);
-    let (pair2, dir2) = (&t[1].0, &t[1].1);
-    let (pair3, dir3) = (&t[2].0, &t[2].1);
-
-    let price1 = BigDecimal::from_str(&spm.get(pair1).map_or("0.0", |(price, _)| price)).unwrap();
-    let price2 = BigDecimal::from_str(&spm.get(pair2).map_or("0.0", |(price, _)| price)).unwrap();
-    let price3 = BigDecimal::from_str(&spm.get(pair3).map_or("0.0", |(price, _)| price)).unwrap();
-
-    // Начинаем с 1 единицы базовой валюты
-    let mut base_amount = BigDecimal::from(1);
-    let base_amount_first = base_amount.clone();
-
-    // Первая сделка
-    if dir1 == "SELL" {
-        base_amount = round_to_scale(base_amount * price1, 10); // базовая валюта продается
-    } else if dir1 == "BUY" {
-        base_amount = round_to_scale(base_amount / price1, 10); // покупаем за базовую валюту
-    }
+// символы из треугольников, по которым еще не пришло ни одной цены
+fn missing_symbols() -> Vec<String> {
+    EXPECTED_SYMBOLS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|symbol| !PRICE_STORAGE.map.contains_key(*symbol))
+        .cloned()
+        .collect()
+}
 
-    // Вторая сделка
-    if dir2 == "SELL" {
-        base_amount = round_to_scale(base_amount * price2, 10); // продажа по второй цене
-    } else if dir2 == "BUY" {
-        base_amount = round_to_scale(base_amount / price2, 10); // покупка по второй цене
+// возраст считается от времени текущего тика, поэтому одинаково работает и в replay
+fn has_stale_leg(spm: &HashMap<String, PriceUpdate>, legs: &[(String, String)], now: i64) -> bool {
+    let max_age = MAX_AGE_US.load(Ordering::Relaxed);
+    if max_age == 0 {
+        return false;
     }
+    legs.iter().any(|(pair, _)| {
+        spm.get(pair)
+            .is_none_or(|update| is_stale(update, now, max_age))
+    })
+}
 
-    // Третья сделка
-    if dir3 == "SELL" {
-        base_amount = round_to_scale(base_amount * price3, 10); // продажа по третьей цене
-    } else if dir3 == "BUY" {
-        base_amount = round_to_scale(base_amount / price3, 10); // покупка по третьей цене
-    }
+// цена старше max_age на момент now; max_age 0 - возраст не проверяется
+fn is_stale(update: &PriceUpdate, now: i64, max_age: i64) -> bool {
+    max_age > 0 && now - update.recv_ts > max_age
+}
+
+// Результат оценки треугольника
+struct Evaluation {
+    final_amount: Fixed, // сколько стартовой валюты возвращается на 1 единицу, без комиссий
+    earn: Fixed,         // валовая доходность, %, earn_scale знаков
+    net_earn: Fixed,     // доходность за вычетом комиссий, %, без округления
+    gross_bps: Fixed,
+    net_bps: Fixed,
+}
 
-    // Вычисление доходности в процентах
-    let earn = round_to_scale(
-        ((base_amount.clone() - base_amount_first.clone()) / base_amount.clone())
-            * BigDecimal::from(100),
-        2,
-    );
+/*
+Прогон стартовой суммы по трем ногам графа: цена и комиссия ноги берутся с
+ее ребра. После каждой ноги остается calc_scale знаков. Доходность считается
+от стартовой суммы: (final - start) / start. Чистая - та же цепочка, где
+каждая нога дополнительно теряет комиссию ребра.
+
+start - объем сделки в стартовой валюте, если известны правила символов:
+тогда количество каждой ноги округляется вниз до шага лота, а нога меньше
+min notional отбрасывает весь треугольник. Без него прогоняется 1 единица
+без ограничений биржи.
+*/
+fn calculate_triangle(
+    graph: &CurrencyGraph,
+    t: &[(String, String)],
+    start: Option<Fixed>,
+    config: &BrainConfig,
+) -> Result<Evaluation, CalcError> {
+    let scale = config.calc_scale;
+    let symbols = start.and(config.symbols.as_deref());
+    let start = start.unwrap_or(Fixed::ONE);
+
+    let mut amount = start;
+    let mut net = start;
+    // сторона каждой ноги - в самой ноге, ключ только именует треугольник
+    for (pair, dir) in t {
+        let edge = graph
+            .leg(pair, dir)
+            .ok_or_else(|| CalcError::MissingPrice(pair.clone()))?;
+        let price = edge.price.clone()?;
+        if price.is_zero() {
+            return Err(CalcError::ZeroPrice(pair.clone()));
+        }
+        let sell = edge.side == Side::Sell;
+        let keep = Fixed::ONE.checked_sub(edge.fee)?;
+        let info = symbols.and_then(|registry| registry.get(pair));
+        let convert = |value: Fixed| -> Result<Fixed, CalcError> {
+            // количество - всегда в базовой валюте пары
+            let Some(info) = info else {
+                return if sell {
+                    Qty::new(value).notional(price) // базовая валюта пары продается
+                } else {
+                    price.qty_for(value).map(Qty::value) // базовая валюта пары покупается
+                };
+            };
+            let qty = if sell {
+                info.round_qty(Qty::new(value))
+            } else {
+                info.round_qty(price.qty_for(value)?)
+            };
+            if !info.accepts(qty, price) {
+                return Err(CalcError::BelowMinNotional(pair.clone()));
+            }
+            if sell {
+                qty.notional(price)
+            } else {
+                Ok(qty.value())
+            }
+        };
+        amount = convert(amount)?.truncate(scale);
+        net = convert(net)?.checked_mul(keep)?.truncate(scale);
+    }
 
-    Some((base_amount, earn))
+    let gross_return = amount.checked_sub(start)?.checked_div(start)?;
+    let net_return = net.checked_sub(start)?.checked_div(start)?;
+    let percent = Fixed::from_int(100);
+    let bps = Fixed::from_int(10_000);
+    let earn_scale = config.earn_scale;
+    Ok(Evaluation {
+        final_amount: amount.checked_div(start)?.truncate(scale),
+        earn: gross_return.checked_mul(percent)?.truncate(earn_scale),
+        net_earn: net_return.checked_mul(percent)?,
+        gross_bps: gross_return.checked_mul(bps)?.truncate(earn_scale),
+        net_bps: net_return.checked_mul(bps)?.truncate(earn_scale),
+    })
 }
 
-pub fn get_nodes_by_label<'a>(
-    graph: &DiGraph<(&'a str, &'a str), ()>,
-    label: &'a str,
-) -> Vec<&'a str> {
+pub fn get_nodes_by_label<E>(graph: &DiGraph<Asset, E>, role: AssetRole) -> Vec<&str> {
     graph
         .node_indices()
         .filter_map(|node_index| {
-            let (value, node_label) = graph[node_index];
-            if node_label == label {
-                Some(value)
+            let asset = &graph[node_index];
+            if asset.role == role {
+                Some(asset.symbol.as_str())
             } else {
                 None
             }
@@ -311,18 +552,24 @@ pub fn get_nodes_by_label<'a>(
         .collect()
 }
 
-pub fn depth_first_search<'a>(
-    graph: &DiGraph<(&'a str, &'a str), ()>,
+/*
+Прежний перебор циклов: выдает каждый цикл во всех поворотах и обоих
+направлениях, дубли убирают triangle_sorting и remove_duplicates, теряя
+порядок обхода. Оставлен для сравнения в бенчмарке, в работе -
+CurrencyGraph::cycles и graph::create_triangles.
+*/
+pub fn depth_first_search<'a, E>(
+    graph: &'a DiGraph<Asset, E>,
     base_nodes: &[&'a str],
     depth: usize,
-) -> Vec<Vec<&'a str>> {
+) -> Result<Vec<Vec<&'a str>>, Error> {
     let mut all_cycles = Vec::new();
 
     for &start_node in base_nodes {
         let start_index = graph
             .node_indices()
-            .find(|&i| graph[i].0 == start_node)
-            .expect("Start node not found");
+            .find(|&i| graph[i].symbol == start_node)
+            .ok_or_else(|| Error::UnknownAsset(start_node.to_string()))?;
 
         let mut stack = VecDeque::new();
         stack.push_back((start_index, vec![start_node]));
@@ -331,7 +578,7 @@ pub fn depth_first_search<'a>(
             if path.len() == depth {
                 if graph
                     .neighbors(current_index)
-                    .any(|neighbor_index| graph[neighbor_index].0 == start_node)
+                    .any(|neighbor_index| graph[neighbor_index].symbol == start_node)
                 {
                     let mut cycle_path = path.clone();
                     cycle_path.push(start_node);
@@ -341,7 +588,7 @@ pub fn depth_first_search<'a>(
             }
 
             for neighbor_index in graph.neighbors(current_index) {
-                let neighbor_value = graph[neighbor_index].0;
+                let neighbor_value = graph[neighbor_index].symbol.as_str();
                 if path.contains(&neighbor_value) {
                     continue;
                 }
@@ -353,7 +600,7 @@ pub fn depth_first_search<'a>(
         }
     }
 
-    all_cycles
+    Ok(all_cycles)
 }
 
 /* создает уникальные и отсортированные треугольники */
@@ -397,3 +644,231 @@ fn generate_random_id(length: usize) -> String {
         .take(length)
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain_sets::{AltCurrency, BaseCurrency, ParsedPairs, Template};
+    use proptest::prelude::*;
+
+    const ASSETS: [&str; 3] = ["USDT", "BTC", "ETH"];
+
+    fn config() -> BrainConfig {
+        BrainConfig {
+            rate: 0.0,
+            signal_min_change: 0.0,
+            signal_cooldown: Duration::ZERO,
+            signal_top_k: 1,
+            signal_exclusive_pairs: false,
+            capital: 0.0,
+            capital_asset: String::new(),
+            max_price_age: None,
+            warmup: WarmupPolicy {
+                min_coverage: 0.0,
+                timeout: None,
+            },
+            calc_scale: fixed::SCALE,
+            earn_scale: 4,
+            fee_rate: 0.0,
+            symbols: None,
+        }
+    }
+
+    // стоимость валюты 2^a * 5^b: курсы между валютами - точные десятичные дроби
+    fn value(exp: (i32, i32)) -> f64 {
+        2f64.powi(exp.0) * 5f64.powi(exp.1)
+    }
+
+    /*
+    Треугольник USDT -> BTC -> ETH -> USDT. Пара каждой ноги в своей ориентации
+    (true - from+to, продаем from), цены согласованы со стоимостями валют,
+    ask выше bid на spread промилле, комиссия ноги - fee_rate.
+    */
+    fn market(
+        values: [(i32, i32); 3],
+        orientation: [bool; 3],
+        spread: [u32; 3],
+        fee_rate: f64,
+    ) -> (CurrencyGraph, Vec<SymbDir>) {
+        let mut updates = Vec::new();
+        let mut legs = Vec::new();
+        for i in 0..3 {
+            let (from, to) = (i, (i + 1) % 3);
+            let (base, quote, dir) = if orientation[i] {
+                (from, to, "SELL")
+            } else {
+                (to, from, "BUY")
+            };
+            let symbol = format!("{}{}", ASSETS[base], ASSETS[quote]);
+            let mid = value(values[base]) / value(values[quote]);
+            let update = PriceUpdate {
+                symbol: symbol.clone(),
+                bid: format!("{:.12}", mid),
+                ask: format!("{:.12}", mid * (1000 + spread[i]) as f64 / 1000.0),
+                bid_qty: "1".to_string(),
+                ask_qty: "1".to_string(),
+                exchange_ts: None,
+                recv_ts: 0,
+                trace: Default::default(),
+            };
+            updates.push(update);
+            legs.push((symbol, dir.to_string()));
+        }
+
+        // все три валюты базовые: граф берет пары между базовыми и alt
+        let base: Vec<BaseCurrency> = ASSETS
+            .iter()
+            .map(|symbol| BaseCurrency {
+                symbol: symbol.to_string(),
+                percentage: 0.0,
+                min_earn: None,
+            })
+            .collect();
+        let alt: Vec<AltCurrency> = ASSETS
+            .iter()
+            .map(|symbol| AltCurrency {
+                symbol: symbol.to_string(),
+            })
+            .collect();
+        let template = Template { ixs: 0, ixe: 0 };
+        let clean: Vec<ParsedPairs> = legs
+            .iter()
+            .map(|(symbol, _)| {
+                ParsedPairs::new(
+                    symbol.clone(),
+                    template.clone(),
+                    template.clone(),
+                    template.clone(),
+                )
+            })
+            .collect();
+        let fee = BigDecimal::from_f64(fee_rate)
+            .and_then(|fee| Fixed::from_bigdecimal(&fee))
+            .unwrap();
+        let mut graph = CurrencyGraph::build(&base, &alt, &clean, None, fee);
+        for update in &updates {
+            assert!(graph.update_prices(update));
+        }
+        (graph, legs)
+    }
+
+    fn exponents() -> impl Strategy<Value = [(i32, i32); 3]> {
+        prop::array::uniform3((-3i32..=3, -3i32..=3))
+    }
+
+    proptest! {
+        #[test]
+        fn identity_prices_give_zero_earn(orientation in prop::array::uniform3(any::<bool>())) {
+            let (graph, legs) = market([(0, 0); 3], orientation, [0; 3], 0.0);
+            let evaluation = calculate_triangle(&graph, &legs, None, &config()).unwrap();
+            prop_assert_eq!(evaluation.final_amount, Fixed::ONE);
+            prop_assert_eq!(evaluation.earn, Fixed::ZERO);
+            prop_assert_eq!(evaluation.net_earn, Fixed::ZERO);
+            prop_assert_eq!(evaluation.gross_bps, Fixed::ZERO);
+        }
+
+        #[test]
+        fn consistent_prices_give_zero_earn(
+            values in exponents(),
+            orientation in prop::array::uniform3(any::<bool>()),
+        ) {
+            let (graph, legs) = market(values, orientation, [0; 3], 0.0);
+            let evaluation = calculate_triangle(&graph, &legs, None, &config()).unwrap();
+            prop_assert_eq!(evaluation.final_amount, Fixed::ONE);
+            prop_assert_eq!(evaluation.earn, Fixed::ZERO);
+        }
+
+        #[test]
+        fn spread_and_fees_never_make_profit(
+            values in exponents(),
+            orientation in prop::array::uniform3(any::<bool>()),
+            spread in prop::array::uniform3(0u32..50),
+            fee_rate in 0.0f64..0.01,
+        ) {
+            let (graph, legs) = market(values, orientation, spread, fee_rate);
+            let evaluation = calculate_triangle(&graph, &legs, None, &config()).unwrap();
+            prop_assert!(evaluation.earn <= Fixed::ZERO);
+            prop_assert!(evaluation.net_earn <= evaluation.earn);
+        }
+    }
+
+    fn key(a: &str, b: &str, c: &str) -> TriangleKey {
+        let legs: Vec<SymbDir> = [a, b, c]
+            .iter()
+            .map(|pair| (pair.to_string(), "SELL".to_string()))
+            .collect();
+        TriangleKey::from_legs(&legs, "USDT").unwrap()
+    }
+
+    // кандидаты тика по (earn, net_earn) треугольников, отбор топа и отметка ушедших, как в react_to_update
+    fn tick(
+        tracker: &mut OpportunityTracker,
+        opportunities: &[(TriangleKey, i64, i64)],
+        config: &BrainConfig,
+        ts: i64,
+    ) -> Vec<TriangleKey> {
+        let mut candidates: Vec<EarnSortedData> = opportunities
+            .iter()
+            .filter_map(|(key, earn, net_earn)| {
+                let result = Some((
+                    BigDecimal::from(1),
+                    BigDecimal::from(*earn),
+                    BigDecimal::from(*net_earn),
+                ));
+                let tracked = tracker.observe(key, result, ts)?;
+                tracked.candidate.then(|| EarnSortedData {
+                    triangle_key: tracked.opportunity.key,
+                    final_amount: tracked.opportunity.final_amount,
+                    earn: tracked.opportunity.earn,
+                    net_earn: tracked.opportunity.net_earn,
+                    gross_bps: BigDecimal::from(0),
+                    net_bps: BigDecimal::from(0),
+                    event: tracked.event,
+                    started_ts: tracked.opportunity.started_ts,
+                    order_size: None,
+                    profit: None,
+                })
+            })
+            .collect();
+        candidates.sort();
+        let selected: Vec<TriangleKey> = select_top(&candidates, config)
+            .into_iter()
+            .map(|data| data.triangle_key.clone())
+            .collect();
+        for key in &selected {
+            tracker.mark_emitted(key, ts);
+        }
+        selected
+    }
+
+    #[test]
+    fn candidate_cut_by_top_k_goes_out_next_tick() {
+        let config = config(); // signal_top_k = 1
+        let mut tracker = OpportunityTracker::new(TrackerConfig {
+            min_change: BigDecimal::from(1),
+            cooldown: Duration::ZERO,
+        });
+        let best = key("ETHUSDT", "ETHBTC", "BTCUSDT");
+        let second = key("BNBUSDT", "BNBBTC", "BTCUSDT");
+        let opportunities = [(best.clone(), 2, 2), (second.clone(), 1, 1)];
+
+        assert_eq!(tick(&mut tracker, &opportunities, &config, 1), [best]);
+        assert_eq!(tick(&mut tracker, &opportunities, &config, 2), [second]);
+        assert!(tick(&mut tracker, &opportunities, &config, 3).is_empty());
+    }
+
+    // порог берет net_earn, и топ тоже: больший валовой earn с дорогими ногами проигрывает
+    #[test]
+    fn top_is_ranked_by_net_earn() {
+        let config = config(); // signal_top_k = 1
+        let mut tracker = OpportunityTracker::new(TrackerConfig {
+            min_change: BigDecimal::from(1),
+            cooldown: Duration::ZERO,
+        });
+        let gross = key("ETHUSDT", "ETHBTC", "BTCUSDT");
+        let net = key("BNBUSDT", "BNBBTC", "BTCUSDT");
+        let opportunities = [(gross, 5, 1), (net.clone(), 3, 2)];
+
+        assert_eq!(tick(&mut tracker, &opportunities, &config, 1), [net]);
+    }
+}
@@ -1,39 +1,336 @@
-use std::sync::{mpsc, Arc, Mutex};
-use std::thread;
+/*
+Рассылка обновлений цен.
 
-pub type Observer = Box<dyn Fn(&String, &String, &String) + Send + Sync>;
+Observable держит broadcast-канал: каждый подписчик получает свою копию
+PriceUpdate и читает в своем темпе. Если подписчик отстал больше чем на
+емкость канала, пропущенные события считаются в его счетчике lagged, а
+сам подписчик пересинхронизируется: получает последние цены всех символов
+(Observable помнит их по символу) и дальше читает только новые события.
+
+Синхронные наблюдатели (add_observer) работают каждый в своем потоке,
+поэтому медленный наблюдатель не задерживает остальных. remove_observer
+будит поток, даже если событий нет, и дожидается его завершения.
+
+Кадры WS разбирают несколько читателей, поэтому два обновления одного
+символа могут прийти в notify_observers не в порядке получения. Обновление,
+полученное раньше уже разосланного (по монотонной метке trace.received),
+отбрасывается: более старая цена не затирает новую ни у подписчиков, ни в
+снимке. Обновления без метки (replay) идут как есть.
+*/
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tokio::runtime;
+use tokio::sync::{broadcast, Notify};
+use tracing::{debug, warn};
+
+use crate::latency::Trace;
+
+const CHANNEL_CAPACITY: usize = 4096;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PriceUpdate {
+    pub symbol: String,
+    pub bid: String,
+    pub ask: String,
+    pub bid_qty: String,
+    pub ask_qty: String,
+    pub exchange_ts: Option<i64>, // время события на бирже, мс
+    pub recv_ts: i64,             // время получения, мкс
+    #[serde(skip)]
+    pub trace: Trace, // метки стадий для latency, в запись не попадают
+}
+
+impl PriceUpdate {
+    // кадр получен раньше other; без метки получения не старше никого
+    pub fn received_before(&self, other: &PriceUpdate) -> bool {
+        self.trace.received > 0 && self.trace.received < other.trace.received
+    }
+}
+
+pub type Observer = Box<dyn Fn(&PriceUpdate) + Send + Sync>;
 
 pub struct Observable {
-    observers: Arc<Mutex<Vec<Observer>>>,
-    sender: mpsc::Sender<(String, String, String)>,
+    sender: broadcast::Sender<PriceUpdate>,
+    latest: Arc<DashMap<String, PriceUpdate>>, // последнее обновление по символу
+}
+
+impl Default for Observable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Observable {
     pub fn new() -> Self {
-        let (sender, receiver) = mpsc::channel();
-        let observers: Arc<Mutex<Vec<Observer>>> = Arc::new(Mutex::new(Vec::new()));
-
-        thread::spawn({
-            let observers = Arc::clone(&observers);
-            move || {
-                for (symbol, price, volume) in receiver {
-                    let observers = observers.lock().unwrap();
-                    for observer in observers.iter() {
-                        observer(&symbol, &price, &volume);
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Observable {
+            sender,
+            latest: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        Subscription {
+            receiver: self.sender.subscribe(),
+            latest: Arc::clone(&self.latest),
+            resync: VecDeque::new(),
+            lagged: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /*
+    Регистрирует синхронного наблюдателя. Наблюдатель работает, пока жив
+    Observable и пока не вызван remove_observer для возвращенного handle.
+    */
+    pub fn add_observer(&self, observer: Observer) -> ObserverHandle {
+        let mut subscription = self.subscribe();
+        let close = Arc::new(Notify::new());
+        let lagged = Arc::clone(&subscription.lagged);
+
+        let closed = Arc::clone(&close);
+        let thread = thread::spawn(move || {
+            let runtime = match runtime::Builder::new_current_thread().build() {
+                Ok(runtime) => runtime,
+                Err(err) => {
+                    warn!("observer runtime: {}", err);
+                    return;
+                }
+            };
+            // ожидание события и сигнала остановки; сам наблюдатель синхронный
+            runtime.block_on(async move {
+                loop {
+                    tokio::select! {
+                        biased;
+                        _ = closed.notified() => break,
+                        update = subscription.recv() => match update {
+                            Some(update) => observer(&update),
+                            None => break,
+                        },
                     }
                 }
-            }
+            });
         });
 
-        Observable { observers, sender }
+        ObserverHandle {
+            close,
+            thread: Arc::new(Mutex::new(Some(thread))),
+            lagged,
+        }
+    }
+
+    // останавливает наблюдателя и ждет его поток; из самого наблюдателя поток не ждем
+    pub fn remove_observer(&self, handle: &ObserverHandle) {
+        handle.close.notify_one();
+        let Some(thread) = handle.thread.lock().unwrap().take() else {
+            return;
+        };
+        if thread.thread().id() != thread::current().id() {
+            let _ = thread.join();
+        }
+    }
+
+    pub fn notify_observers(&self, update: PriceUpdate) {
+        // запись символа держится до отправки: обновления одного символа уходят по порядку
+        let _held = match self.latest.entry(update.symbol.clone()) {
+            Entry::Occupied(entry) if update.received_before(entry.get()) => {
+                debug!("{}: out of order update dropped", update.symbol);
+                return;
+            }
+            Entry::Occupied(mut entry) => {
+                entry.insert(update.clone());
+                entry.into_ref()
+            }
+            Entry::Vacant(entry) => entry.insert(update.clone()),
+        };
+        // ошибка означает только отсутствие подписчиков
+        let _ = self.sender.send(update);
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    // сколько событий еще не прочитал самый медленный подписчик
+    pub fn pending(&self) -> usize {
+        self.sender.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        CHANNEL_CAPACITY
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ObserverHandle {
+    close: Arc<Notify>,
+    thread: Arc<Mutex<Option<JoinHandle<()>>>>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl ObserverHandle {
+    // сколько событий наблюдатель пропустил из-за отставания
+    pub fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Subscription {
+    receiver: broadcast::Receiver<PriceUpdate>,
+    latest: Arc<DashMap<String, PriceUpdate>>,
+    resync: VecDeque<PriceUpdate>, // снимок последних цен после отставания
+    lagged: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    // None - Observable закрыт
+    pub async fn recv(&mut self) -> Option<PriceUpdate> {
+        loop {
+            if let Some(update) = self.resync.pop_front() {
+                return Some(update);
+            }
+            match self.receiver.recv().await {
+                Ok(update) => return Some(update),
+                Err(broadcast::error::RecvError::Lagged(n)) => self.on_lag(n),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    pub fn blocking_recv(&mut self) -> Option<PriceUpdate> {
+        loop {
+            if let Some(update) = self.resync.pop_front() {
+                return Some(update);
+            }
+            match self.receiver.blocking_recv() {
+                Ok(update) => return Some(update),
+                Err(broadcast::error::RecvError::Lagged(n)) => self.on_lag(n),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    pub fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
     }
 
-    pub fn add_observer(&mut self, observer: Observer) {
-        let mut observers = self.observers.lock().unwrap();
-        observers.push(observer);
+    /*
+    Пропущенные события не вернуть, но последняя цена каждого символа есть в
+    latest. Сначала подписка переходит на хвост канала, потом снимается
+    снимок: событие между ними придет дважды, но не потеряется.
+    */
+    fn on_lag(&mut self, skipped: u64) {
+        let total = self.lagged.fetch_add(skipped, Ordering::Relaxed) + skipped;
+        warn!(
+            "subscriber lagged: skipped {} updates ({} total), resyncing {} symbols",
+            skipped,
+            total,
+            self.latest.len()
+        );
+        self.receiver = self.receiver.resubscribe();
+        let mut snapshot: Vec<PriceUpdate> = self
+            .latest
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        snapshot.sort_by_key(|update| update.recv_ts);
+        self.resync = snapshot.into();
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::{Duration, Instant};
+
+    fn update(symbol: &str, bid: &str, recv_ts: i64) -> PriceUpdate {
+        PriceUpdate {
+            symbol: symbol.to_string(),
+            bid: bid.to_string(),
+            ask: bid.to_string(),
+            bid_qty: "1".to_string(),
+            ask_qty: "1".to_string(),
+            exchange_ts: None,
+            recv_ts,
+            trace: Trace::default(),
+        }
+    }
+
+    #[test]
+    fn lagged_subscriber_gets_latest_price_of_every_symbol() {
+        let observable = Observable::new();
+        let mut subscription = observable.subscribe();
+
+        observable.notify_observers(update("ETHUSDT", "3000", 1));
+        let flood = CHANNEL_CAPACITY as i64 + 10;
+        for ts in 2..=flood {
+            observable.notify_observers(update("BTCUSDT", &ts.to_string(), ts));
+        }
+
+        // ETHUSDT вытеснен из канала, но приходит из снимка, BTCUSDT - последний
+        let first = subscription.blocking_recv().unwrap();
+        let second = subscription.blocking_recv().unwrap();
+        assert!(subscription.lagged() > 0);
+        assert_eq!(
+            (first.symbol.as_str(), first.bid.as_str()),
+            ("ETHUSDT", "3000")
+        );
+        assert_eq!(second.symbol, "BTCUSDT");
+        assert_eq!(second.bid, flood.to_string());
+
+        observable.notify_observers(update("BTCUSDT", "next", flood + 1));
+        assert_eq!(subscription.blocking_recv().unwrap().bid, "next");
+    }
+
+    #[test]
+    fn removed_observer_thread_stops_without_new_updates() {
+        let observable = Observable::new();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&seen);
+        let handle = observable.add_observer(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        observable.notify_observers(update("BTCUSDT", "1", 1));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while seen.load(Ordering::SeqCst) == 0 {
+            assert!(Instant::now() < deadline, "observer got nothing");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // поток ждет событий; remove_observer будит его и дожидается выхода
+        observable.remove_observer(&handle);
+        assert_eq!(observable.subscriber_count(), 0);
+        observable.notify_observers(update("BTCUSDT", "2", 2));
+        assert_eq!(seen.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn older_frame_of_a_symbol_does_not_overwrite_newer() {
+        let observable = Observable::new();
+        let mut subscription = observable.subscribe();
+        let received = |bid: &str, received: i64| {
+            let mut update = update("BTCUSDT", bid, received);
+            update.trace.received = received;
+            update
+        };
+
+        // второй читатель разобрал кадр, полученный раньше, уже после нового
+        observable.notify_observers(received("new", 20));
+        observable.notify_observers(received("old", 10));
+        observable.notify_observers(received("newer", 30));
+
+        assert_eq!(subscription.blocking_recv().unwrap().bid, "new");
+        assert_eq!(subscription.blocking_recv().unwrap().bid, "newer");
+        assert_eq!(observable.latest.get("BTCUSDT").unwrap().bid, "newer");
 
-    pub fn notify_observers(&self, symbol: String, price: String, volume: String) {
-        self.sender.send((symbol, price, volume)).unwrap();
+        // у записи replay меток нет: порядок файла
+        observable.notify_observers(update("BTCUSDT", "replayed", 1));
+        assert_eq!(subscription.blocking_recv().unwrap().bid, "replayed");
     }
 }
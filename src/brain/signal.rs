@@ -0,0 +1,185 @@
+use bigdecimal::BigDecimal;
+use chrono::Local;
+use tracing::info;
+
+use super::generate_random_id;
+use super::tracker::{Opportunity, OpportunityEvent};
+use super::triangle::TriangleKey;
+use crate::latency::Trace;
+use crate::uds_write::UdsWriter;
+
+// Сигнал о найденной возможности, который движок отдает наружу
+#[derive(Clone, Debug)]
+pub struct Signal {
+    pub triangle_key: TriangleKey,
+    pub final_amount: BigDecimal,
+    pub earn: BigDecimal,               // доходность от стартовой суммы, %
+    pub gross_bps: BigDecimal,          // та же доходность в базисных пунктах
+    pub net_bps: BigDecimal,            // за вычетом комиссий трех ног, bps
+    pub event: OpportunityEvent,        // Open или Update
+    pub started_ts: i64,                // когда возможность открылась, мкс
+    pub order_size: Option<BigDecimal>, // рекомендуемый объем в стартовой валюте
+    pub profit: Option<BigDecimal>, // прибыль на order_size за вычетом комиссий, в стартовой валюте
+    pub ts: i64,                    // recv_ts обновления, на котором найден сигнал, мкс
+    pub trace: Trace,               // метки стадий обновления до расчета
+}
+
+// Куда движок отправляет сигналы: UDS в боевом режиме, отчет в режиме replay
+pub trait SignalSink: Send + Sync {
+    // лучшие треугольники тика, по убыванию earn, одним пакетом
+    fn emit(&self, batch: &[Signal]);
+
+    // возможность закрылась (earn ушел ниже порога)
+    fn closed(&self, _opportunity: &Opportunity) {}
+}
+
+pub struct UdsSignalSink {
+    writer: UdsWriter,
+    uid: String,
+}
+
+impl UdsSignalSink {
+    pub fn new(writer: UdsWriter) -> Self {
+        UdsSignalSink {
+            writer,
+            uid: generate_random_id(4),
+        }
+    }
+}
+
+impl SignalSink for UdsSignalSink {
+    // первая строка пакета - как раньше MAX, остальные помечены местом в топе
+    fn emit(&self, batch: &[Signal]) {
+        let current_time = Local::now();
+        let formatted_time = current_time.format("%H:%M:%S%.6f");
+
+        let mut msg_to_arm = String::new();
+        for (rank, signal) in batch.iter().enumerate() {
+            let label = if rank == 0 {
+                "MAX".to_string()
+            } else {
+                format!("TOP{}", rank + 1)
+            };
+            let size = signal
+                .order_size
+                .as_ref()
+                .map_or("-".to_string(), |size| size.with_scale(6).to_string());
+            let profit = signal
+                .profit
+                .as_ref()
+                .map_or("-".to_string(), |profit| profit.with_scale(6).to_string());
+            msg_to_arm.push_str(&format!(
+                "{}  {} {} -> {:?}, Final Amount: {}, Earn: {}, Gross: {} bps, Net: {} bps, Start: {}, Size: {}, Profit: {}, Trace: {}\n",
+                self.uid,
+                formatted_time,
+                label,
+                signal.triangle_key,
+                signal.final_amount.with_scale(6),
+                signal.earn,
+                signal.gross_bps,
+                signal.net_bps,
+                signal.triangle_key.start,
+                size,
+                profit,
+                stamps(&signal.trace)
+            ));
+        }
+
+        info!(target: "signals", "{}", msg_to_arm.trim_end());
+
+        // у строк свои метки, в гистограммы задержек идет тик, на котором собран пакет
+        self.writer.send_traced(msg_to_arm, batch[0].trace);
+    }
+}
+
+// метки стадий сигнала, мкс; written проставит UdsWriter уже после записи строки
+fn stamps(trace: &Trace) -> String {
+    let exchange = trace
+        .exchange_us
+        .map_or("-".to_string(), |micros| micros.to_string());
+    format!(
+        "exchange_us={} received={} dequeued={} parsed={} observed={} calculated={}",
+        exchange, trace.received, trace.dequeued, trace.parsed, trace.observed, trace.calculated
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::UnixListener;
+
+    fn signal(start: &str, trace: Trace) -> Signal {
+        Signal {
+            triangle_key: TriangleKey {
+                a: "BTCUSDT".to_string(),
+                b: "ETHBTC".to_string(),
+                c: "ETHUSDT".to_string(),
+                d: "BUY".to_string(),
+                start: start.to_string(),
+            },
+            final_amount: BigDecimal::from(1),
+            earn: BigDecimal::from(1),
+            gross_bps: BigDecimal::from(100),
+            net_bps: BigDecimal::from(70),
+            event: OpportunityEvent::Open,
+            started_ts: 1,
+            order_size: None,
+            profit: None,
+            ts: 1,
+            trace,
+        }
+    }
+
+    #[tokio::test]
+    async fn every_line_carries_its_trace() {
+        let path = std::env::temp_dir().join(format!("ws-signal-trace-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path = path.to_string_lossy().to_string();
+        let listener = UnixListener::bind(&path).unwrap();
+        let writer = UdsWriter::spawn(&path).unwrap();
+
+        let first = Trace {
+            exchange_us: Some(7),
+            received: 10,
+            dequeued: 11,
+            parsed: 12,
+            observed: 13,
+            calculated: 14,
+            written: 0,
+        };
+        let second = Trace {
+            exchange_us: None,
+            received: 20,
+            ..first
+        };
+        let sink = UdsSignalSink::new(writer.clone());
+        sink.emit(&[signal("USDT", first), signal("BTC", second)]);
+        drop(sink);
+        tokio::time::timeout(Duration::from_secs(5), writer.finish())
+            .await
+            .unwrap();
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).await.unwrap();
+        let lines: Vec<&str> = received.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", received);
+        assert!(
+            lines[0].ends_with(
+                "Trace: exchange_us=7 received=10 dequeued=11 parsed=12 observed=13 calculated=14"
+            ),
+            "{}",
+            lines[0]
+        );
+        assert!(
+            lines[1].ends_with(
+                "Trace: exchange_us=- received=20 dequeued=11 parsed=12 observed=13 calculated=14"
+            ),
+            "{}",
+            lines[1]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+/*
+Состояние движка для HTTP /status и /metrics.
+Часы у движка и /status одни - latency::wall_us, которым помечается recv_ts.
+Движок берет их на тике (recv_ts текущего обновления), /status - в момент
+запроса: если поток цен встал целиком, /status должен показать все символы
+устаревшими, а самое позднее полученное обновление при этом тоже стоит на месте.
+*/
+use dashmap::DashMap;
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+
+use super::observer::PriceUpdate;
+use super::warmup::{coverage, WarmupState};
+use super::{
+    is_stale, missing_symbols, COUNT, MAX_AGE_US, PRICE_STORAGE, PROCESSED, REGULAR_MODE,
+    STALE_SKIPS, TRACKER, TRIANGLE_KEYS, WARMUP,
+};
+use crate::latency;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EngineStatus {
+    pub regular_mode: bool,
+    pub warmup: WarmupState,
+    pub coverage: f64,           // % ожидаемых символов с ценой
+    pub symbols: usize,          // символов с ценой
+    pub expected_symbols: usize, // сколько ждем до начала работы
+    pub missing_symbols: Vec<String>,
+    pub ready_triangles: usize, // треугольники, у всех ног которых есть цена
+    pub total_triangles: usize,
+    pub processed_updates: u64,
+    pub open_opportunities: usize,
+    pub max_price_age_ms: Option<u64>,
+    pub stale_symbols: Vec<String>,
+    pub stale_skips: u64, // сколько раз треугольник пропущен из-за устаревшей ноги
+}
+
+pub fn status() -> EngineStatus {
+    let max_age_us = MAX_AGE_US.load(Ordering::SeqCst);
+    let symbols = PRICE_STORAGE.count();
+    let expected_symbols = COUNT.load(Ordering::SeqCst);
+    let (ready_triangles, total_triangles) = triangle_readiness();
+    EngineStatus {
+        regular_mode: REGULAR_MODE.load(Ordering::SeqCst),
+        warmup: WARMUP.lock().unwrap().state(),
+        coverage: coverage(symbols, expected_symbols),
+        symbols,
+        expected_symbols,
+        missing_symbols: missing_symbols(),
+        ready_triangles,
+        total_triangles,
+        processed_updates: PROCESSED.load(Ordering::SeqCst),
+        open_opportunities: TRACKER.lock().unwrap().open_count(),
+        max_price_age_ms: (max_age_us > 0).then_some(max_age_us as u64 / 1000),
+        stale_symbols: stale_symbols(),
+        stale_skips: STALE_SKIPS.load(Ordering::Relaxed),
+    }
+}
+
+fn triangle_readiness() -> (usize, usize) {
+    let keys = TRIANGLE_KEYS.read().unwrap();
+    let ready = keys
+        .iter()
+        .filter(|key| {
+            [&key.a, &key.b, &key.c]
+                .iter()
+                .all(|pair| PRICE_STORAGE.map.contains_key(*pair))
+        })
+        .count();
+    (ready, keys.len())
+}
+
+pub fn stale_symbols() -> Vec<String> {
+    stale_at(
+        &PRICE_STORAGE.map,
+        MAX_AGE_US.load(Ordering::SeqCst),
+        latency::wall_us(),
+    )
+}
+
+// символы, цена которых на момент now старше max_age_us, по алфавиту
+fn stale_at(prices: &DashMap<String, PriceUpdate>, max_age_us: i64, now: i64) -> Vec<String> {
+    let mut stale: Vec<String> = prices
+        .iter()
+        .filter(|entry| is_stale(entry.value(), now, max_age_us))
+        .map(|entry| entry.key().clone())
+        .collect();
+    stale.sort();
+    stale
+}
+
+impl EngineStatus {
+    // текстовый формат Prometheus
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, help: &str, kind: &str, value: String| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        metric(
+            "ws_regular_mode",
+            "1 when warm-up is over and triangles are evaluated",
+            "gauge",
+            (self.regular_mode as u8).to_string(),
+        );
+        metric(
+            "ws_warmup_coverage_percent",
+            "Share of expected symbols that already have a price",
+            "gauge",
+            format!("{:.2}", self.coverage),
+        );
+        metric(
+            "ws_ready_triangles",
+            "Triangles whose legs all have prices",
+            "gauge",
+            self.ready_triangles.to_string(),
+        );
+        metric(
+            "ws_price_symbols",
+            "Symbols with at least one price",
+            "gauge",
+            self.symbols.to_string(),
+        );
+        metric(
+            "ws_processed_updates_total",
+            "Price updates processed by the engine",
+            "counter",
+            self.processed_updates.to_string(),
+        );
+        metric(
+            "ws_open_opportunities",
+            "Triangles currently above their earn threshold",
+            "gauge",
+            self.open_opportunities.to_string(),
+        );
+        metric(
+            "ws_stale_symbols",
+            "Symbols whose last price is older than max_price_age",
+            "gauge",
+            self.stale_symbols.len().to_string(),
+        );
+        metric(
+            "ws_stale_skips_total",
+            "Triangle evaluations skipped because of a stale leg",
+            "counter",
+            self.stale_skips.to_string(),
+        );
+        let _ = writeln!(out, "# HELP ws_stale_symbol Stale symbol marker");
+        let _ = writeln!(out, "# TYPE ws_stale_symbol gauge");
+        for symbol in &self.stale_symbols {
+            let _ = writeln!(out, "ws_stale_symbol{{symbol=\"{}\"}} 1", symbol);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(symbol: &str, recv_ts: i64) -> PriceUpdate {
+        PriceUpdate {
+            symbol: symbol.to_string(),
+            bid: "1".to_string(),
+            ask: "1".to_string(),
+            bid_qty: "1".to_string(),
+            ask_qty: "1".to_string(),
+            exchange_ts: None,
+            recv_ts,
+            trace: Default::default(),
+        }
+    }
+
+    #[test]
+    fn price_older_than_max_age_is_stale() {
+        let prices = DashMap::new();
+        for update in [update("STALEUSDT", 1_000), update("FRESHUSDT", 10_000)] {
+            prices.insert(update.symbol.clone(), update);
+        }
+
+        assert_eq!(stale_at(&prices, 1_000, 10_500), ["STALEUSDT"]);
+        assert_eq!(stale_at(&prices, 1_000, 20_000), ["FRESHUSDT", "STALEUSDT"]);
+        assert!(stale_at(&prices, 0, 20_000).is_empty());
+    }
+}
@@ -0,0 +1,304 @@
+/*
+Отслеживание жизни возможностей по треугольникам.
+
+Треугольник "открыт", пока его net_earn (за вычетом комиссий) держится не
+ниже порога. Трекер фиксирует открытие, обновления и закрытие, время начала,
+пиковый earn и длительность. Наружу сигнал идет только при открытии и при
+существенном изменении net_earn (не меньше min_change), и не чаще одного
+раза в cooldown для одного треугольника.
+
+observe только предлагает кандидата на сигнал: до исполнителя доходят не
+все (top_k, exclusive_pairs). Сигнал считается отправленным после
+mark_emitted, до этого кандидат повторяется на следующих тиках.
+*/
+use bigdecimal::BigDecimal;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::triangle::TriangleKey;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpportunityEvent {
+    Open,
+    Update,
+    Close,
+}
+
+#[derive(Clone, Debug)]
+pub struct Opportunity {
+    pub key: TriangleKey,
+    pub started_ts: i64, // мкс
+    pub last_ts: i64,    // мкс
+    pub final_amount: BigDecimal,
+    pub earn: BigDecimal,     // валовой, %
+    pub net_earn: BigDecimal, // за вычетом комиссий, %
+    pub peak_earn: BigDecimal,
+    pub updates: u64,
+}
+
+impl Opportunity {
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros((self.last_ts - self.started_ts).max(0) as u64)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TrackerConfig {
+    pub min_change: BigDecimal, // в процентных пунктах net_earn
+    pub cooldown: Duration,
+}
+
+#[derive(Debug)]
+pub struct Tracked {
+    pub event: OpportunityEvent,
+    pub opportunity: Opportunity,
+    pub candidate: bool, // кандидат на сигнал; ушел ли он, решает отбор топа
+}
+
+struct OpenState {
+    opportunity: Opportunity,
+    emitted_earn: Option<BigDecimal>, // net_earn ушедшего сигнала
+}
+
+pub struct OpportunityTracker {
+    config: TrackerConfig,
+    open: HashMap<TriangleKey, OpenState>,
+    last_emit: HashMap<TriangleKey, i64>,
+}
+
+impl OpportunityTracker {
+    pub fn new(config: TrackerConfig) -> Self {
+        OpportunityTracker {
+            config,
+            open: HashMap::new(),
+            last_emit: HashMap::new(),
+        }
+    }
+
+    /*
+    Результат оценки треугольника на тике ts.
+    result - (final_amount, earn, net_earn), если net_earn не ниже порога, иначе None.
+    */
+    pub fn observe(
+        &mut self,
+        key: &TriangleKey,
+        result: Option<(BigDecimal, BigDecimal, BigDecimal)>,
+        ts: i64,
+    ) -> Option<Tracked> {
+        let Some((final_amount, earn, net_earn)) = result else {
+            let state = self.open.remove(key)?;
+            let mut opportunity = state.opportunity;
+            opportunity.last_ts = ts;
+            return Some(Tracked {
+                event: OpportunityEvent::Close,
+                opportunity,
+                candidate: false,
+            });
+        };
+
+        let cooled = self
+            .last_emit
+            .get(key)
+            .is_none_or(|last| ts - last >= self.config.cooldown.as_micros() as i64);
+
+        let (event, state) = match self.open.entry(key.clone()) {
+            Entry::Occupied(entry) => {
+                let state = entry.into_mut();
+                let opportunity = &mut state.opportunity;
+                if earn > opportunity.peak_earn {
+                    opportunity.peak_earn = earn.clone();
+                }
+                opportunity.final_amount = final_amount;
+                opportunity.earn = earn;
+                opportunity.net_earn = net_earn;
+                opportunity.last_ts = ts;
+                opportunity.updates += 1;
+                (OpportunityEvent::Update, state)
+            }
+            Entry::Vacant(entry) => {
+                let state = OpenState {
+                    opportunity: Opportunity {
+                        key: key.clone(),
+                        started_ts: ts,
+                        last_ts: ts,
+                        final_amount,
+                        earn: earn.clone(),
+                        net_earn,
+                        peak_earn: earn,
+                        updates: 0,
+                    },
+                    emitted_earn: None,
+                };
+                (OpportunityEvent::Open, entry.insert(state))
+            }
+        };
+
+        // открытие, которое не ушло из-за cooldown, уйдет первым же обновлением после него
+        let material = match &state.emitted_earn {
+            None => true,
+            Some(emitted) => {
+                (&state.opportunity.net_earn - emitted).abs() >= self.config.min_change
+            }
+        };
+
+        Some(Tracked {
+            event,
+            opportunity: state.opportunity.clone(),
+            candidate: cooled && material,
+        })
+    }
+
+    // сигнал по треугольнику ушел на тике ts: от него отсчитываются cooldown и min_change
+    pub fn mark_emitted(&mut self, key: &TriangleKey, ts: i64) {
+        if let Some(state) = self.open.get_mut(key) {
+            state.emitted_earn = Some(state.opportunity.net_earn.clone());
+            self.last_emit.insert(key.clone(), ts);
+        }
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.open.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn key() -> TriangleKey {
+        TriangleKey {
+            a: "BTCUSDT".to_string(),
+            b: "ETHBTC".to_string(),
+            c: "ETHUSDT".to_string(),
+            d: "BUY".to_string(),
+            start: "USDT".to_string(),
+        }
+    }
+
+    // комиссий нет: net_earn равен earn
+    fn earn(value: &str) -> Option<(BigDecimal, BigDecimal, BigDecimal)> {
+        let earn = BigDecimal::from_str(value).unwrap();
+        let final_amount = BigDecimal::from(1) + &earn / BigDecimal::from(100);
+        Some((final_amount, earn.clone(), earn))
+    }
+
+    fn tracker(min_change: &str, cooldown_ms: u64) -> OpportunityTracker {
+        OpportunityTracker::new(TrackerConfig {
+            min_change: BigDecimal::from_str(min_change).unwrap(),
+            cooldown: Duration::from_millis(cooldown_ms),
+        })
+    }
+
+    #[test]
+    fn opportunity_opens_updates_and_closes() {
+        let mut tracker = tracker("0", 0);
+        assert!(tracker.observe(&key(), None, 500).is_none());
+
+        let open = tracker.observe(&key(), earn("0.2"), 1_000).unwrap();
+        assert_eq!(open.event, OpportunityEvent::Open);
+        assert_eq!(tracker.open_count(), 1);
+        tracker.observe(&key(), earn("0.5"), 2_000).unwrap();
+        let update = tracker.observe(&key(), earn("0.3"), 3_000).unwrap();
+        assert_eq!(update.event, OpportunityEvent::Update);
+        assert_eq!(update.opportunity.updates, 2);
+        assert_eq!(
+            update.opportunity.peak_earn,
+            BigDecimal::from_str("0.5").unwrap()
+        );
+        assert_eq!(
+            update.opportunity.earn,
+            BigDecimal::from_str("0.3").unwrap()
+        );
+
+        let close = tracker.observe(&key(), None, 4_500).unwrap();
+        assert_eq!(close.event, OpportunityEvent::Close);
+        assert!(!close.candidate);
+        assert_eq!(close.opportunity.started_ts, 1_000);
+        assert_eq!(close.opportunity.duration(), Duration::from_micros(3_500));
+        assert_eq!(tracker.open_count(), 0);
+
+        // после закрытия та же пара открывается заново
+        let reopen = tracker.observe(&key(), earn("0.2"), 5_000).unwrap();
+        assert_eq!(reopen.event, OpportunityEvent::Open);
+        assert_eq!(reopen.opportunity.started_ts, 5_000);
+    }
+
+    #[test]
+    fn candidate_needs_material_change_after_emit() {
+        let mut tracker = tracker("0.1", 0);
+        assert!(tracker.observe(&key(), earn("0.2"), 1).unwrap().candidate);
+        // не отправлен: кандидат повторяется
+        assert!(tracker.observe(&key(), earn("0.2"), 2).unwrap().candidate);
+        tracker.mark_emitted(&key(), 2);
+
+        assert!(!tracker.observe(&key(), earn("0.25"), 3).unwrap().candidate);
+        assert!(tracker.observe(&key(), earn("0.3"), 4).unwrap().candidate);
+        assert!(tracker.observe(&key(), earn("0.1"), 5).unwrap().candidate);
+    }
+
+    // валовой earn вырос, а за вычетом комиссий почти нет: это не новость
+    #[test]
+    fn material_change_is_judged_by_net_earn() {
+        let mut tracker = tracker("0.1", 0);
+        let reading = |earn: &str, net_earn: &str| {
+            Some((
+                BigDecimal::from(1),
+                BigDecimal::from_str(earn).unwrap(),
+                BigDecimal::from_str(net_earn).unwrap(),
+            ))
+        };
+        tracker.observe(&key(), reading("0.5", "0.2"), 1).unwrap();
+        tracker.mark_emitted(&key(), 1);
+
+        let gross_jump = tracker.observe(&key(), reading("0.9", "0.25"), 2).unwrap();
+        assert!(!gross_jump.candidate);
+        assert_eq!(
+            gross_jump.opportunity.earn,
+            BigDecimal::from_str("0.9").unwrap()
+        );
+        assert!(
+            tracker
+                .observe(&key(), reading("0.9", "0.3"), 3)
+                .unwrap()
+                .candidate
+        );
+    }
+
+    #[test]
+    fn cooldown_holds_candidates_of_the_same_triangle() {
+        let mut tracker = tracker("0", 10);
+        assert!(tracker.observe(&key(), earn("0.2"), 0).unwrap().candidate);
+        tracker.mark_emitted(&key(), 0);
+
+        assert!(
+            !tracker
+                .observe(&key(), earn("0.5"), 9_999)
+                .unwrap()
+                .candidate
+        );
+        assert!(
+            tracker
+                .observe(&key(), earn("0.5"), 10_000)
+                .unwrap()
+                .candidate
+        );
+
+        // cooldown переживает закрытие: новое открытие тоже ждет
+        tracker.mark_emitted(&key(), 10_000);
+        tracker.observe(&key(), None, 11_000);
+        assert!(
+            !tracker
+                .observe(&key(), earn("0.2"), 12_000)
+                .unwrap()
+                .candidate
+        );
+        assert!(
+            tracker
+                .observe(&key(), earn("0.2"), 20_000)
+                .unwrap()
+                .candidate
+        );
+    }
+}
@@ -7,19 +7,58 @@ pub struct TriangleKey {
     pub b: String,
     pub c: String,
     pub d: String,
-} //Triangle Key: TriangleKey { a: "ETHBTC", b: "ETHUSDT", c: "BTCUSDT", d: "SELL" }
+    pub start: String, // стартовая базовая валюта, в ней считается прибыль
+} //Triangle Key: TriangleKey { a: "ETHUSDT", b: "ETHBTC", c: "BTCUSDT", d: "BUY", start: "USDT" }
+
+impl TriangleKey {
+    // ключ по ногам в порядке сделок; d - сторона первой ноги, для вывода
+    pub fn from_legs(legs: &[TriangleElement], start: &str) -> Option<Self> {
+        let [(a, d), (b, _), (c, _)] = legs else {
+            return None;
+        };
+        Some(TriangleKey {
+            a: a.clone(),
+            b: b.clone(),
+            c: c.clone(),
+            d: d.clone(),
+            start: start.to_string(),
+        })
+    }
+}
 
 impl fmt::Display for TriangleKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // Определяем, как выводить поля структуры
         write!(
             f,
-            "TriangleKey(a: {}, b: {}, c: {}, d: {})",
-            self.a, self.b, self.c, self.d
+            "TriangleKey(a: {}, b: {}, c: {}, d: {}, start: {})",
+            self.a, self.b, self.c, self.d, self.start
         )
     }
 }
 
+// Пара на ребре графа: явные базовая и котируемая валюты, без разбора строки символа
+#[derive(Hash, Eq, PartialEq, Debug, Clone)]
+pub struct PairAssets {
+    pub symbol: String,
+    pub base: String,  // BTC в BTCUSDT
+    pub quote: String, // USDT в BTCUSDT
+    pub guessed: bool, // правил символов нет, base/quote угаданы по склейке символа
+}
+
+impl PairAssets {
+    // вторая валюта пары; None - asset в паре не участвует
+    pub fn other(&self, asset: &str) -> Option<&str> {
+        if self.base == asset {
+            Some(&self.quote)
+        } else if self.quote == asset {
+            Some(&self.base)
+        } else {
+            None
+        }
+    }
+}
+
 pub type TriangleElement = (
     String, // symbol
     String, // direction
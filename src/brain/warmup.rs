@@ -0,0 +1,107 @@
+/*
+Прогрев движка после старта.
+
+Движок начинает оценивать треугольники, когда цены есть не меньше чем у
+min_coverage процентов ожидаемых символов, или когда с первого обновления
+прошло timeout. Дальше каждый треугольник считается, как только у всех трех
+его ног появилась цена, так что одна неторгуемая пара не держит остальные.
+Время считается по recv_ts обновлений, поэтому в replay прогрев тот же.
+*/
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+#[derive(Clone, Debug)]
+pub struct WarmupPolicy {
+    pub min_coverage: f64, // %, 0 - треугольники считаются сразу по готовности
+    pub timeout: Option<Duration>, // None - ждать покрытия без ограничения
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmupState {
+    Warming,
+    Ready,    // покрытие достигнуто
+    TimedOut, // вышел timeout, работаем с тем, что есть
+}
+
+pub struct Warmup {
+    policy: WarmupPolicy,
+    state: WarmupState,
+    first_ts: Option<i64>,
+    last_priced: usize,
+}
+
+impl Warmup {
+    pub fn new(policy: WarmupPolicy) -> Self {
+        Warmup {
+            policy,
+            state: WarmupState::Warming,
+            first_ts: None,
+            last_priced: 0,
+        }
+    }
+
+    pub fn state(&self) -> WarmupState {
+        self.state
+    }
+
+    pub fn policy(&self) -> &WarmupPolicy {
+        &self.policy
+    }
+
+    /*
+    Вызывается на каждом обновлении, пока идет прогрев.
+    priced/expected - сколько символов уже с ценой и сколько ждем,
+    missing - список символов без цены (нужен только при выходе по timeout).
+    Возвращает true, когда прогрев закончен.
+    */
+    pub fn on_update(
+        &mut self,
+        ts: i64,
+        priced: usize,
+        expected: usize,
+        missing: impl FnOnce() -> Vec<String>,
+    ) -> bool {
+        if self.state != WarmupState::Warming {
+            return true;
+        }
+        let first_ts = *self.first_ts.get_or_insert(ts);
+        let coverage = coverage(priced, expected);
+
+        if priced != self.last_priced {
+            self.last_priced = priced;
+            debug!(
+                "warm-up: {}/{} symbols priced ({:.1}%)",
+                priced, expected, coverage
+            );
+        }
+
+        if coverage >= self.policy.min_coverage {
+            self.state = WarmupState::Ready;
+            info!(
+                "warm-up done: {}/{} symbols priced ({:.1}%)",
+                priced, expected, coverage
+            );
+        } else if let Some(timeout) = self.policy.timeout {
+            if ts - first_ts >= timeout.as_micros() as i64 {
+                self.state = WarmupState::TimedOut;
+                warn!(
+                    "warm-up timed out after {:?} at {:.1}% coverage, still without prices: {}",
+                    timeout,
+                    coverage,
+                    missing().join(", ")
+                );
+            }
+        }
+
+        self.state != WarmupState::Warming
+    }
+}
+
+pub fn coverage(priced: usize, expected: usize) -> f64 {
+    if expected == 0 {
+        return 100.0;
+    }
+    (priced as f64 / expected as f64 * 100.0).min(100.0)
+}
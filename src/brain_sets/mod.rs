@@ -1,8 +1,9 @@
-use std::fmt::Debug;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
+use crate::error::{Error, Result};
+
 #[derive(Clone, Debug)]
 pub struct Template {
     pub ixs: usize, //str_index_start
@@ -11,27 +12,13 @@ pub struct Template {
 
 const COUNT_FIELD_PS: usize = 1; //колво полей в разделе AltCurrency
 const COUNT_FIELD_CI: usize = 2; //колво полей в разделе AltCurrency
-
-pub(crate) trait Currency: Debug {
-    fn symbol(&self) -> &str;
-}
-
-impl Currency for AltCurrency {
-    fn symbol(&self) -> &str {
-        &self.symbol
-    }
-}
-
-impl Currency for BaseCurrency {
-    fn symbol(&self) -> &str {
-        &self.symbol
-    }
-}
+const COUNT_FIELD_CI_MAX: usize = 3; //с необязательным минимальным earn
 
 #[derive(Debug)]
 pub struct BaseCurrency {
     pub symbol: String,
-    pub percentage: f32,
+    pub percentage: f32,       //доля капитала под эту валюту, %
+    pub min_earn: Option<f32>, //свой порог earn, %; None - общий response_rate
 }
 
 #[derive(Debug)]
@@ -62,63 +49,83 @@ impl ParsedPairs {
         }
     }
 }
-pub fn read_setting_base_currency<P: AsRef<Path>>(path: P) -> Vec<BaseCurrency> {
-    let file = File::open(path).expect("Failed to open file");
+pub fn read_setting_base_currency<P: AsRef<Path>>(path: P) -> Result<Vec<BaseCurrency>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|err| Error::io(path, err))?;
     let reader = BufReader::new(file);
 
     let mut data = Vec::new();
     let mut inside_section = false;
 
-    for line in reader.lines() {
-        let line = line.expect("Failed to read line");
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| Error::io(path, err))?;
 
         if line.trim() == "[> BaseCurrency >]" {
             inside_section = true;
         } else if line.trim() == "[< BaseCurrency <]" {
             inside_section = false;
-        } else if inside_section {
-            if !line.trim_start().starts_with(";") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() == COUNT_FIELD_CI {
-                    let symbol = parts[0].to_string();
-                    let percentage = parts[1]
-                        .trim_end_matches('%')
-                        .parse::<f32>()
-                        .expect("Invalid percentage format");
-
-                    let info = BaseCurrency { symbol, percentage };
-                    data.push(info);
-                }
+        } else if inside_section && !line.trim_start().starts_with(";") {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if (COUNT_FIELD_CI..=COUNT_FIELD_CI_MAX).contains(&parts.len()) {
+                let symbol = parts[0].to_string();
+                let percentage = parse_percent(parts[1]).ok_or_else(|| {
+                    Error::settings(
+                        path,
+                        index + 1,
+                        format!("invalid percentage {:?} for {}", parts[1], symbol),
+                    )
+                })?;
+                let min_earn = match parts.get(2) {
+                    Some(value) => Some(parse_percent(value).ok_or_else(|| {
+                        Error::settings(
+                            path,
+                            index + 1,
+                            format!("invalid min earn {:?} for {}", value, symbol),
+                        )
+                    })?),
+                    None => None,
+                };
+
+                let info = BaseCurrency {
+                    symbol,
+                    percentage,
+                    min_earn,
+                };
+                data.push(info);
             }
         }
     }
-    data
+    Ok(data)
 }
 
-pub fn read_setting_alt_currency<P: AsRef<Path>>(path: P) -> Vec<AltCurrency> {
-    let file = File::open(path).expect("Failed to open file");
+pub fn read_setting_alt_currency<P: AsRef<Path>>(path: P) -> Result<Vec<AltCurrency>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|err| Error::io(path, err))?;
     let reader = BufReader::new(file);
 
     let mut data = Vec::new();
     let mut inside_section = false;
 
     for line in reader.lines() {
-        let line = line.expect("Failed to read line");
+        let line = line.map_err(|err| Error::io(path, err))?;
 
         if line.trim() == "[> AltCurrency >]" {
             inside_section = true;
         } else if line.trim() == "[< AltCurrency <]" {
             inside_section = false;
-        } else if inside_section {
-            if !line.trim_start().starts_with(";") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() == COUNT_FIELD_PS {
-                    let symbol = parts[0].to_string();
-                    let info = AltCurrency { symbol };
-                    data.push(info);
-                }
+        } else if inside_section && !line.trim_start().starts_with(";") {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() == COUNT_FIELD_PS {
+                let symbol = parts[0].to_string();
+                let info = AltCurrency { symbol };
+                data.push(info);
             }
         }
     }
-    data
+    Ok(data)
+}
+
+// "12.5%" или "12.5"
+fn parse_percent(value: &str) -> Option<f32> {
+    value.trim_end_matches('%').parse::<f32>().ok()
 }
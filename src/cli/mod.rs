@@ -0,0 +1,377 @@
+/*
+Командная строка бинаря ws.
+
+    ws run              живой движок: WS, разбор bookTicker, brain, сигналы в UDS, HTTP
+    ws graph            граф из файла настроек: пары, циклы и треугольники (text, dot, json)
+    ws check-config     проверить настройки и окружение, все проблемы одним списком
+    ws replay <file>    прогнать запись Recorder через движок и вывести отчет бэктеста
+    ws send <msg>       отправить сообщение в /send_message работающего экземпляра
+
+ws run останавливается по Ctrl-C: WS закрывается, читатели дочитывают
+очередь кадров, запись рыночных данных дописывается, движок останавливается,
+UdsWriter дописывает сигналы (не дольше SHUTDOWN_TIMEOUT).
+
+Флаги конфигурации (--config, --set, --print-config) общие для всех команд.
+С --print-config команда не выполняется: выводится итоговая конфигурация
+и ее проблемы.
+*/
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::brain::export::{self, ExportFormat, GraphExport};
+use crate::brain::graph::Side;
+use crate::brain::initialize_observers;
+use crate::brain::market::Market;
+use crate::brain::observer::Observable;
+use crate::brain::signal::UdsSignalSink;
+use crate::config::{self, Config, ConfigArgs};
+use crate::error::{Error, Result};
+use crate::http_server::{HttpServer, HttpServerConfig};
+use crate::latency::{self, Trace};
+use crate::logging;
+use crate::queue::{INCOMING_QUEUE, OUTCOMING_QUEUE};
+use crate::recorder::Recorder;
+use crate::replay::{backtest, BacktestConfig, ReplaySpeed};
+use crate::uds_write::UdsWriter;
+use crate::websocket_client::{book_ticker, WebSocketClient};
+
+const SUBSCRIBE_CHUNK: usize = 200; // символов в одном сообщении SUBSCRIBE
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Parser)]
+#[command(
+    name = "ws",
+    version,
+    about = "Треугольный арбитраж: движок и инструменты"
+)]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Запустить живой движок
+    Run,
+    /// Построить граф из файла настроек и вывести пары, циклы и треугольники
+    Graph {
+        #[arg(long, value_enum, default_value_t = GraphFormat::Text)]
+        format: GraphFormat,
+    },
+    /// Проверить настройки и окружение
+    CheckConfig,
+    /// Прогнать запись рыночных данных через движок
+    Replay {
+        file: PathBuf,
+        /// original, max или множитель: 10x
+        #[arg(long, default_value = "max", value_parser = parse_speed)]
+        speed: ReplaySpeed,
+        /// Условный объем сделки в стартовой валюте для PnL
+        #[arg(long, default_value_t = 100.0)]
+        notional: f64,
+    },
+    /// Отправить сообщение в /send_message работающего экземпляра
+    Send {
+        message: String,
+        /// Адрес, по умолчанию http://127.0.0.1:<http.port>/send_message
+        #[arg(long)]
+        url: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GraphFormat {
+    Text,
+    Dot,
+    Json,
+}
+
+impl Command {
+    // ключи, без которых команда не работает
+    fn required(&self) -> &'static [&'static str] {
+        match self {
+            Command::Run | Command::CheckConfig => config::REQUIRED,
+            Command::Graph { .. } | Command::Replay { .. } => &["brain.settings"],
+            Command::Send { .. } => &[],
+        }
+    }
+}
+
+pub fn execute(cli: Cli) -> Result<()> {
+    let required = cli.command.required();
+    if cli.config.print_config {
+        let (config, problems) = config::inspect(&cli.config, required);
+        print!("{}", config.print());
+        return if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config(problems))
+        };
+    }
+
+    let config = config::init(&cli.config, required)?;
+    logging::init(&config.log);
+    match cli.command {
+        Command::Run => run(config),
+        Command::Graph { format } => graph(&config, format),
+        Command::CheckConfig => check_config(&config),
+        Command::Replay {
+            file,
+            speed,
+            notional,
+        } => replay(&config, &file, speed, notional),
+        Command::Send { message, url } => send(&config, &message, url),
+    }
+}
+
+fn parse_speed(value: &str) -> std::result::Result<ReplaySpeed, String> {
+    ReplaySpeed::parse(value).ok_or_else(|| format!("invalid speed {:?}", value))
+}
+
+fn load_market(config: &Config) -> Result<Market> {
+    let brain = config.brain_config();
+    Market::load(
+        &config.brain.settings,
+        brain.symbols.as_deref(),
+        brain.fee_rate,
+    )
+}
+
+fn runtime() -> Result<tokio::runtime::Runtime> {
+    tokio::runtime::Runtime::new().map_err(|err| Error::io("tokio runtime", err))
+}
+
+fn run(config: Config) -> Result<()> {
+    runtime()?.block_on(run_engine(config))
+}
+
+async fn run_engine(config: Config) -> Result<()> {
+    let brain = config.brain_config();
+    let market = load_market(&config)?;
+    info!(
+        "market: {} pairs, {} cycles, {} triangles",
+        market.pairs.len(),
+        market.cycles.len(),
+        market.triangles.len()
+    );
+    export::publish(market.graph.clone(), market.cycles.clone(), &brain);
+
+    let socket = &config.sinks.uds_socket;
+    let writer = UdsWriter::spawn(socket).map_err(|err| Error::io(socket, err))?;
+    let observable = Arc::new(Observable::new());
+    let engine = initialize_observers(
+        &observable,
+        &market,
+        &brain,
+        Arc::new(UdsSignalSink::new(writer.clone())),
+    );
+
+    // запись живет, пока работает движок; при остановке файл дописывается
+    let recorder = match config.recorder() {
+        Some(recorder) => {
+            let dir = recorder.dir.clone();
+            Some(
+                Recorder::start(recorder, &OUTCOMING_QUEUE, &observable)
+                    .map_err(|err| Error::io(dir, err))?,
+            )
+        }
+        None => None,
+    };
+
+    let client = WebSocketClient::new(&config.ws.url).await?;
+    if config.ws.auto_subscription {
+        for (id, chunk) in market.pairs.chunks(SUBSCRIBE_CHUNK).enumerate() {
+            client.send_message(&book_ticker::subscribe_message(chunk, id as u64 + 1));
+        }
+    }
+
+    // читатели кадров: разбор bookTicker и рассылка в движок
+    let mut readers = Vec::new();
+    for index in 0..config.ws.reader_count.max(1) {
+        let observable = Arc::clone(&observable);
+        let reader = thread::Builder::new()
+            .name(format!("ws-reader-{}", index))
+            .spawn(move || {
+                while let Some((text, stamp)) = OUTCOMING_QUEUE.pop_stamped() {
+                    let dequeued = latency::now_us();
+                    if let Some(mut update) = book_ticker::parse(&text, stamp.wall_us) {
+                        update.trace = Trace {
+                            exchange_us: update.exchange_ts.map(|ts| stamp.exchange_delay(ts)),
+                            received: stamp.mono_us,
+                            dequeued,
+                            parsed: latency::now_us(),
+                            ..Trace::default()
+                        };
+                        observable.notify_observers(update);
+                    }
+                }
+            })
+            .map_err(|err| Error::io("ws reader thread", err))?;
+        readers.push(reader);
+    }
+
+    let server = HttpServer::start(HttpServerConfig {
+        port: config.http.port,
+        client: Arc::clone(&client),
+        incoming_queue: INCOMING_QUEUE.clone(),
+    })
+    .await;
+    tokio::select! {
+        _ = server.await_completion() => {}
+        signal = tokio::signal::ctrl_c() => {
+            if let Err(err) = signal {
+                return Err(Error::io("ctrl-c handler", err));
+            }
+            info!("shutting down");
+        }
+    }
+
+    if let Err(err) = client.close() {
+        warn!("ws close: {}", err);
+    }
+    tokio::task::block_in_place(|| {
+        OUTCOMING_QUEUE.close();
+        for reader in readers {
+            if reader.join().is_err() {
+                warn!("ws reader thread panicked");
+            }
+        }
+        if let Some(recorder) = recorder {
+            recorder.stop(&OUTCOMING_QUEUE, &observable);
+        }
+        // поток движка кончается вместе с UdsSignalSink и его копией UdsWriter
+        observable.remove_observer(&engine);
+    });
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, writer.finish())
+        .await
+        .is_err()
+    {
+        warn!("uds writer did not finish in {:?}", SHUTDOWN_TIMEOUT);
+    }
+    Ok(())
+}
+
+fn graph(config: &Config, format: GraphFormat) -> Result<()> {
+    let market = load_market(config)?;
+    let export_format = match format {
+        GraphFormat::Text => {
+            print!("{}", describe(&market));
+            return Ok(());
+        }
+        GraphFormat::Dot => ExportFormat::Dot,
+        GraphFormat::Json => ExportFormat::Json,
+    };
+    let export = GraphExport::new(&market.graph, &market.cycles, None);
+    println!("{}", export.render(export_format));
+    Ok(())
+}
+
+// пары, циклы и треугольники в читаемом виде, в стабильном порядке (ws graph)
+pub fn describe(market: &Market) -> String {
+    let mut out = format!("pairs ({}):\n", market.pairs.len());
+    for pair in &market.pairs {
+        // без exchangeInfo base/quote пары угаданы по символу
+        let guessed = market
+            .graph
+            .leg(pair, Side::Sell.as_str())
+            .is_some_and(|edge| edge.pair.guessed);
+        let mark = if guessed { " (guessed)" } else { "" };
+        out.push_str(&format!("  {}{}\n", pair, mark));
+    }
+
+    out.push_str(&format!("cycles ({}):\n", market.cycles.len()));
+    for cycle in &market.cycles {
+        let assets: Vec<&str> = cycle.iter().map(String::as_str).collect();
+        let pairs: Vec<String> = market
+            .graph
+            .path_edges(&assets)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|edge| edge.pair.symbol.clone())
+            .collect();
+        out.push_str(&format!(
+            "  {}  [{}]\n",
+            assets.join(" - "),
+            pairs.join(", ")
+        ));
+    }
+
+    let mut triangles: Vec<_> = market.triangles.iter().collect();
+    triangles.sort_by_key(|(key, _)| (key.start.clone(), key.a.clone(), key.b.clone()));
+    out.push_str(&format!("triangles ({}):\n", triangles.len()));
+    for (key, legs) in triangles {
+        let legs: Vec<String> = legs
+            .iter()
+            .map(|(symbol, side)| format!("{} {}", symbol, side))
+            .collect();
+        out.push_str(&format!("  {}: {}\n", key.start, legs.join(" -> ")));
+    }
+    out
+}
+
+fn check_config(config: &Config) -> Result<()> {
+    let market = load_market(config)?;
+    if market.triangles.is_empty() {
+        return Err(Error::Config(vec![format!(
+            "brain.settings: {}: no triangles can be built from these currencies",
+            config.brain.settings
+        )]));
+    }
+    println!(
+        "configuration ok: {} base, {} alt, {} pairs, {} cycles, {} triangles",
+        market.base.len(),
+        market.alt.len(),
+        market.pairs.len(),
+        market.cycles.len(),
+        market.triangles.len()
+    );
+    Ok(())
+}
+
+fn replay(config: &Config, file: &PathBuf, speed: ReplaySpeed, notional: f64) -> Result<()> {
+    let brain = config.brain_config();
+    let market = load_market(config)?;
+    let report = backtest(
+        file,
+        &market,
+        &brain,
+        &BacktestConfig {
+            speed,
+            fee_rate: brain.fee_rate,
+            notional,
+        },
+    )
+    .map_err(|err| Error::io(file, err))?;
+    print!("{}", report);
+    Ok(())
+}
+
+fn send(config: &Config, message: &str, url: Option<String>) -> Result<()> {
+    let url = url.unwrap_or_else(|| format!("http://127.0.0.1:{}/send_message", config.http.port));
+    let http_error = |message: String| Error::Http {
+        url: url.clone(),
+        message,
+    };
+    runtime()?.block_on(async {
+        let response = reqwest::Client::new()
+            .post(&url)
+            .body(message.to_string())
+            .send()
+            .await
+            .map_err(|err| http_error(err.to_string()))?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if status.is_success() {
+            println!("{}", body);
+            Ok(())
+        } else {
+            Err(http_error(format!("{} {}", status, body)))
+        }
+    })
+}
@@ -1,61 +1,874 @@
+/*
+Конфигурация движка.
+
+Слои, каждый следующий перекрывает предыдущий:
+    значения по умолчанию < TOML-файл (--config) < окружение < --set key=value
+Ключ - путь в секциях: ws.url, http.port, brain.fee_rate, sinks.record.dir.
+Переменные окружения - прежние плоские имена (wss_url, http_port, ...),
+так что старые .env-файлы работают как раньше.
+
+Значение, которое не разбирается, неизвестный ключ или пропущенный
+обязательный - ошибка, а не молчаливая подмена на default. Все проблемы
+собираются в один Error::Config. print() показывает итоговые значения и
+слой, откуда взято каждое; вывод сам является годным TOML.
+*/
+use clap::Args;
 use dotenv::from_filename;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use toml::{Table, Value};
+use url::Url;
+
+use crate::brain::fixed;
+use crate::brain::warmup::WarmupPolicy;
+use crate::brain::BrainConfig;
+use crate::brain_sets::{read_setting_alt_currency, read_setting_base_currency};
+use crate::error::{Error, Result};
+use crate::logging::{self, LogFormat};
+use crate::recorder::format::RecordFormat;
+use crate::recorder::RecorderConfig;
+use crate::symbols::SymbolRegistry;
+use crate::uds_write::DEFAULT_SOCKET_PATH;
+
+// ключ конфигурации и прежнее имя переменной окружения
+const KEYS: &[(&str, &str)] = &[
+    ("log.filter", "log_filter"),
+    ("log.format", "log_format"),
+    ("log.span_timing", "log_span_timing"),
+    ("ws.url", "wss_url"),
+    ("ws.ping_interval", "ping_interval"),
+    ("ws.reader_count", "reader_count"),
+    ("ws.volume_accept", "volume_accept"),
+    ("ws.auto_subscription", "auto_subscription"),
+    ("http.port", "http_port"),
+    ("brain.settings", "brain"),
+    ("brain.response_rate", "response_rate"),
+    ("brain.signal_min_change", "signal_min_change"),
+    ("brain.signal_cooldown_ms", "signal_cooldown_ms"),
+    ("brain.signal_top_k", "signal_top_k"),
+    ("brain.signal_exclusive_pairs", "signal_exclusive_pairs"),
+    ("brain.capital", "capital"),
+    ("brain.capital_asset", "capital_asset"),
+    ("brain.max_price_age_ms", "max_price_age_ms"),
+    ("brain.warmup_min_coverage", "warmup_min_coverage"),
+    ("brain.warmup_timeout_secs", "warmup_timeout_secs"),
+    ("brain.calc_scale", "calc_scale"),
+    ("brain.earn_scale", "earn_scale"),
+    ("brain.fee_rate", "fee_rate"),
+    ("brain.exchange_info", "exchange_info"),
+    ("sinks.uds_socket", "uds_socket"),
+    ("sinks.record.on", "record_on"),
+    ("sinks.record.dir", "record_dir"),
+    ("sinks.record.format", "record_format"),
+    ("sinks.record.rotate_mb", "record_rotate_mb"),
+    ("sinks.record.rotate_secs", "record_rotate_secs"),
+    ("sinks.record.zstd", "record_zstd"),
+];
 
+// без них живой движок не запустить, default тут - лишь заглушка
+pub const REQUIRED: &[&str] = &[
+    "ws.url",
+    "ws.ping_interval",
+    "ws.reader_count",
+    "brain.settings",
+];
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub tracing_on: bool,
+    pub log: LogSection,
+    pub ws: WsSection,
+    pub http: HttpSection,
+    pub brain: BrainSection,
+    pub sinks: SinksSection,
+    #[serde(skip)]
+    pub sources: BTreeMap<String, Source>, // ключ - откуда взято значение
+    #[serde(skip)]
+    pub symbols: Option<Arc<SymbolRegistry>>, // правила символов из brain.exchange_info
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSection {
+    pub filter: String, // директивы EnvFilter, меняются на лету через HTTP /log/filter
+    pub format: LogFormat,
+    pub span_timing: bool, // писать время закрытых спанов
+}
+
+impl Default for LogSection {
+    fn default() -> Self {
+        LogSection {
+            filter: "info".to_string(),
+            format: LogFormat::Text,
+            span_timing: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WsSection {
+    pub url: String,
     pub ping_interval: u16,
-    pub brain: String,
     pub reader_count: u8,
-    pub wss_url: String,
-    pub http_port: u16,
     pub volume_accept: bool,
     pub auto_subscription: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpSection {
+    pub port: u16,
+}
+
+impl Default for HttpSection {
+    fn default() -> Self {
+        HttpSection { port: 8080 }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrainSection {
+    pub settings: String, // файл с секциями BaseCurrency и AltCurrency
     pub response_rate: f64,
+    pub signal_min_change: f64,
+    pub signal_cooldown_ms: u64,
+    pub signal_top_k: usize,
+    pub signal_exclusive_pairs: bool,
+    pub capital: f64,
+    pub capital_asset: String,
+    pub max_price_age_ms: u64, // 0 - возраст цен не проверяется
+    pub warmup_min_coverage: f64,
+    pub warmup_timeout_secs: u64, // 0 - без ограничения
+    pub calc_scale: u32,
+    pub earn_scale: u32,
+    pub fee_rate: f64,
+    pub exchange_info: String, // файл exchangeInfo с правилами символов, "" - без правил
+}
+
+impl Default for BrainSection {
+    fn default() -> Self {
+        BrainSection {
+            settings: String::new(),
+            response_rate: 1.0,
+            signal_min_change: 0.05,
+            signal_cooldown_ms: 0,
+            signal_top_k: 1,
+            signal_exclusive_pairs: false,
+            capital: 0.0,
+            capital_asset: "USDT".to_string(),
+            max_price_age_ms: 0,
+            warmup_min_coverage: 0.0,
+            warmup_timeout_secs: 60,
+            calc_scale: 10,
+            earn_scale: 2,
+            fee_rate: 0.0,
+            exchange_info: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SinksSection {
+    pub uds_socket: String, // куда UdsSignalSink пишет сигналы
+    pub record: RecordSection,
+}
+
+impl Default for SinksSection {
+    fn default() -> Self {
+        SinksSection {
+            uds_socket: DEFAULT_SOCKET_PATH.to_string(),
+            record: RecordSection::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordSection {
+    pub on: bool,
+    pub dir: String,
+    pub format: RecordFormat,
+    pub rotate_mb: u64,   // 0 - без ротации по размеру
+    pub rotate_secs: u64, // 0 - без ротации по времени
+    pub zstd: bool,
+}
+
+impl Default for RecordSection {
+    fn default() -> Self {
+        RecordSection {
+            on: false,
+            dir: "./md_records".to_string(),
+            format: RecordFormat::Binary,
+            rotate_mb: 256,
+            rotate_secs: 3600,
+            zstd: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "file {}", path.display()),
+            Source::Env(name) => write!(f, "env {}", name),
+            Source::Cli => write!(f, "cli --set"),
+        }
+    }
+}
+
+// флаги конфигурации, общие для всех команд
+#[derive(Clone, Debug, Default, Args)]
+pub struct ConfigArgs {
+    /// TOML-файл конфигурации
+    #[arg(long, short = 'c', value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+    /// Переопределить значение, например --set http.port=9090
+    #[arg(long = "set", short = 's', value_name = "KEY=VALUE", global = true)]
+    pub overrides: Vec<String>,
+    /// Показать итоговую конфигурацию и откуда взято каждое значение
+    #[arg(long, global = true)]
+    pub print_config: bool,
+}
+
+impl Config {
+    pub fn brain_config(&self) -> BrainConfig {
+        let brain = &self.brain;
+        BrainConfig {
+            rate: brain.response_rate,
+            signal_min_change: brain.signal_min_change,
+            signal_cooldown: Duration::from_millis(brain.signal_cooldown_ms),
+            signal_top_k: brain.signal_top_k,
+            signal_exclusive_pairs: brain.signal_exclusive_pairs,
+            capital: brain.capital,
+            capital_asset: brain.capital_asset.clone(),
+            max_price_age: (brain.max_price_age_ms > 0)
+                .then(|| Duration::from_millis(brain.max_price_age_ms)),
+            warmup: WarmupPolicy {
+                min_coverage: brain.warmup_min_coverage,
+                timeout: (brain.warmup_timeout_secs > 0)
+                    .then(|| Duration::from_secs(brain.warmup_timeout_secs)),
+            },
+            calc_scale: brain.calc_scale,
+            earn_scale: brain.earn_scale,
+            fee_rate: brain.fee_rate,
+            symbols: self.symbols.clone(),
+        }
+    }
+
+    /*
+    Проверки, которым нужны значения целиком: обязательные для команды ключи,
+    адрес WS, файл настроек валют, диапазоны. Пустой список - конфигурация
+    годная. Файл правил символов проверяет load_symbols, читая его.
+    */
+    pub fn validate(&self, required: &[&str]) -> Vec<String> {
+        let mut problems = Vec::new();
+        for key in required {
+            if self.source(key) == &Source::Default {
+                problems.push(format!("{}: must be set (env {})", key, env_name(key)));
+            }
+        }
+        if let Err(err) = logging::parse_filter(&self.log.filter) {
+            problems.push(format!("log.filter: {:?}: {}", self.log.filter, err));
+        }
+        if !self.ws.url.is_empty() {
+            if let Err(err) = Url::parse(&self.ws.url) {
+                problems.push(format!("ws.url: invalid url {:?}: {}", self.ws.url, err));
+            }
+        }
+        let settings = &self.brain.settings;
+        if !settings.is_empty() {
+            match read_setting_base_currency(settings) {
+                Ok(base) => {
+                    if base.is_empty() {
+                        problems.push(format!(
+                            "brain.settings: {}: no BaseCurrency entries",
+                            settings
+                        ));
+                    }
+                    for currency in base.iter().filter(|currency| currency.percentage < 0.0) {
+                        problems.push(format!(
+                            "brain.settings: {}: BaseCurrency {} percentage {}% must be 0 or more",
+                            settings, currency.symbol, currency.percentage
+                        ));
+                    }
+                    let total: f32 = base.iter().map(|currency| currency.percentage).sum();
+                    if total > 100.0 {
+                        problems.push(format!(
+                            "brain.settings: {}: BaseCurrency percentages sum to {}%",
+                            settings, total
+                        ));
+                    }
+                }
+                Err(err) => problems.push(format!("brain.settings: {}", err)),
+            }
+            match read_setting_alt_currency(settings) {
+                Ok(alt) if alt.is_empty() => problems.push(format!(
+                    "brain.settings: {}: no AltCurrency entries",
+                    settings
+                )),
+                Ok(_) => {}
+                // файл уже не открылся выше, вторая строка про то же не нужна
+                Err(Error::Io { .. }) => {}
+                Err(err) => problems.push(format!("brain.settings: {}", err)),
+            }
+        }
+        let brain = &self.brain;
+        for (key, value) in [
+            ("brain.response_rate", brain.response_rate),
+            ("brain.signal_min_change", brain.signal_min_change),
+            ("brain.capital", brain.capital),
+        ] {
+            if !(0.0..).contains(&value) {
+                problems.push(format!("{}: {} must be 0 or more", key, value));
+            }
+        }
+        for (key, value) in [
+            ("brain.calc_scale", brain.calc_scale),
+            ("brain.earn_scale", brain.earn_scale),
+        ] {
+            if value > fixed::SCALE {
+                problems.push(format!(
+                    "{}: {} is more than {} digits of the fixed-point calculation",
+                    key,
+                    value,
+                    fixed::SCALE
+                ));
+            }
+        }
+        if !(0.0..1.0).contains(&self.brain.fee_rate) {
+            problems.push(format!(
+                "brain.fee_rate: {} is not in [0, 1)",
+                self.brain.fee_rate
+            ));
+        }
+        if !(0.0..=100.0).contains(&self.brain.warmup_min_coverage) {
+            problems.push(format!(
+                "brain.warmup_min_coverage: {} is not in [0, 100]",
+                self.brain.warmup_min_coverage
+            ));
+        }
+        if self.brain.signal_top_k == 0 {
+            problems.push("brain.signal_top_k: must be at least 1".to_string());
+        }
+        problems
+    }
+
+    // откуда взято значение ключа
+    pub fn source(&self, key: &str) -> &Source {
+        self.sources.get(key).unwrap_or(&Source::Default)
+    }
+
+    // итоговая конфигурация: "ключ = значение  # слой", по строке на ключ
+    pub fn print(&self) -> String {
+        let table = to_table(self);
+        let mut out = String::new();
+        for (key, _) in KEYS {
+            if let Some(value) = lookup(&table, key) {
+                out.push_str(&format!("{} = {}  # {}\n", key, value, self.source(key)));
+            }
+        }
+        out
+    }
+
+    // правила символов читаются один раз при сборке конфигурации; Err - проблема для списка
+    fn load_symbols(&mut self) -> std::result::Result<(), String> {
+        let exchange_info = &self.brain.exchange_info;
+        if exchange_info.is_empty() {
+            return Ok(());
+        }
+        let registry = SymbolRegistry::load(exchange_info)
+            .map_err(|err| format!("brain.exchange_info: {}: {}", exchange_info, err))?;
+        self.symbols = Some(Arc::new(registry));
+        Ok(())
+    }
+
+    // None - запись рыночных данных выключена
+    pub fn recorder(&self) -> Option<RecorderConfig> {
+        let record = &self.sinks.record;
+        if !record.on {
+            return None;
+        }
+        Some(RecorderConfig {
+            dir: PathBuf::from(&record.dir),
+            format: record.format,
+            rotate_bytes: (record.rotate_mb > 0).then(|| record.rotate_mb * 1024 * 1024),
+            rotate_every: (record.rotate_secs > 0).then(|| Duration::from_secs(record.rotate_secs)),
+            zstd: record.zstd,
+            raw: true,
+            updates: true,
+        })
+    }
+}
+
+/*
+Собирает конфигурацию по слоям. Окружение перед чтением дополняется
+.env-файлом (PRODUCTION_ENV_FILE, по умолчанию .env.development).
+*/
+pub fn init(args: &ConfigArgs, required: &[&str]) -> Result<Config> {
+    load(args, &env_vars(), required)
+}
+
+// как init, но конфигурация возвращается и с проблемами - для --print-config и check-config
+pub fn inspect(args: &ConfigArgs, required: &[&str]) -> (Config, Vec<String>) {
+    resolve(args, &env_vars(), required)
 }
 
-pub async fn init() -> Config {
+// слои без обращения к окружению процесса: env_vars - пары (имя, значение)
+pub fn load(args: &ConfigArgs, env_vars: &[(String, String)], required: &[&str]) -> Result<Config> {
+    let (config, problems) = resolve(args, env_vars, required);
+    if problems.is_empty() {
+        Ok(config)
+    } else {
+        Err(Error::Config(problems))
+    }
+}
+
+fn env_vars() -> Vec<(String, String)> {
     let env_file =
         env::var("PRODUCTION_ENV_FILE").unwrap_or_else(|_| ".env.development".to_string());
     from_filename(&env_file).ok();
 
-    let tracing_on_str = env::var("tracing_on").unwrap_or("false".to_string());
-    let tracing_on: bool = tracing_on_str.parse().unwrap_or(false);
+    KEYS.iter()
+        .filter_map(|(_, name)| env::var(name).ok().map(|value| (name.to_string(), value)))
+        .collect()
+}
+
+fn resolve(
+    args: &ConfigArgs,
+    env_vars: &[(String, String)],
+    required: &[&str],
+) -> (Config, Vec<String>) {
+    let mut layers = Layers::new();
+
+    if let Some(path) = &args.config {
+        match read_file(path) {
+            Ok(file) => {
+                let source = Source::File(path.clone());
+                for (key, value) in flatten(&file) {
+                    let Some(default) = layers.default(&key) else {
+                        layers.problem(&key, &source, "unknown key".to_string());
+                        continue;
+                    };
+                    match coerce(value, default) {
+                        Some(value) => layers.set(&key, value, &source),
+                        None => {
+                            let message = format!("expected {}, got {}", default.type_str(), value);
+                            layers.problem(&key, &source, message);
+                        }
+                    }
+                }
+            }
+            Err(err) => layers.problems.push(err),
+        }
+    }
+
+    for (name, text) in env_vars {
+        let Some((key, _)) = KEYS.iter().find(|(_, env)| env == name) else {
+            continue;
+        };
+        layers.set_text(key, text, &Source::Env(name.clone()));
+    }
+
+    for item in &args.overrides {
+        match item.split_once('=') {
+            Some((key, text)) => layers.set_text(key.trim(), text, &Source::Cli),
+            None => layers
+                .problems
+                .push(format!("--set {:?}: expected KEY=VALUE", item)),
+        }
+    }
+
+    let Layers {
+        merged,
+        sources,
+        mut problems,
+        ..
+    } = layers;
+    // каждое значение уже проверено set, сборка целиком не падает
+    let mut config: Config = match Value::Table(merged).try_into() {
+        Ok(config) => config,
+        Err(err) => {
+            problems.push(err.message().to_string());
+            Config::default()
+        }
+    };
+    config.sources = sources;
+    if let Err(problem) = config.load_symbols() {
+        problems.push(problem);
+    }
+    problems.extend(config.validate(required));
+    (config, problems)
+}
+
+// накопление слоев: итоговая таблица, источники ключей и проблемы
+struct Layers {
+    defaults: Table,
+    merged: Table,
+    sources: BTreeMap<String, Source>,
+    problems: Vec<String>,
+}
+
+impl Layers {
+    fn new() -> Self {
+        let defaults = to_table(&Config::default());
+        Layers {
+            merged: defaults.clone(),
+            defaults,
+            sources: BTreeMap::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    fn default(&self, key: &str) -> Option<&Value> {
+        lookup(&self.defaults, key)
+    }
+
+    fn problem(&mut self, key: &str, source: &Source, message: String) {
+        self.problems
+            .push(format!("{}: {}: {}", key, source, message));
+    }
+
+    // строка из окружения или --set
+    fn set_text(&mut self, key: &str, text: &str, source: &Source) {
+        let Some(default) = self.default(key) else {
+            self.problem(key, source, "unknown key".to_string());
+            return;
+        };
+        match parse_value(text, default) {
+            Some(value) => self.set(key, value, source),
+            None => {
+                let message = format!("expected {}, got {:?}", default.type_str(), text);
+                self.problem(key, source, message);
+            }
+        }
+    }
+
+    /*
+    Значение пробуется отдельно на значениях по умолчанию: так диапазоны
+    (u16 для порта и т.п.) и формат записи проверяет serde, а в отчет
+    попадает ключ и слой, откуда пришло плохое значение.
+    */
+    fn set(&mut self, key: &str, value: Value, source: &Source) {
+        let mut probe = self.defaults.clone();
+        assign(&mut probe, key, value.clone());
+        if let Err(err) = Value::Table(probe).try_into::<Config>() {
+            self.problem(key, source, err.message().to_string());
+            return;
+        }
+        assign(&mut self.merged, key, value);
+        self.sources.insert(key.to_string(), source.clone());
+    }
+}
+
+fn env_name(key: &str) -> &'static str {
+    KEYS.iter()
+        .find(|(k, _)| *k == key)
+        .map_or("", |(_, name)| name)
+}
+
+// ошибка - готовая строка отчета
+fn read_file(path: &PathBuf) -> std::result::Result<Table, String> {
+    let text = fs::read_to_string(path).map_err(|err| Error::io(path, err).to_string())?;
+    toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err.message()))
+}
+
+fn to_table(config: &Config) -> Table {
+    match Value::try_from(config) {
+        Ok(Value::Table(table)) => table,
+        _ => Table::new(),
+    }
+}
+
+// вложенные таблицы в пары "a.b.c" - значение
+fn flatten(table: &Table) -> Vec<(String, &Value)> {
+    let mut out = Vec::new();
+    for (name, value) in table {
+        match value {
+            Value::Table(inner) => {
+                for (key, value) in flatten(inner) {
+                    out.push((format!("{}.{}", name, key), value));
+                }
+            }
+            _ => out.push((name.clone(), value)),
+        }
+    }
+    out
+}
+
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let (section, rest) = match key.split_once('.') {
+        Some((section, rest)) => (section, Some(rest)),
+        None => (key, None),
+    };
+    match (table.get(section)?, rest) {
+        (Value::Table(inner), Some(rest)) => lookup(inner, rest),
+        (Value::Table(_), None) => None,
+        (value, None) => Some(value),
+        (_, Some(_)) => None,
+    }
+}
+
+fn assign(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((section, rest)) => {
+            if let Some(Value::Table(inner)) = table.get_mut(section) {
+                assign(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+// значение из файла к типу default; целое годится и для дробного ключа
+fn coerce(value: &Value, default: &Value) -> Option<Value> {
+    match (default, value) {
+        (Value::Float(_), Value::Integer(int)) => Some(Value::Float(*int as f64)),
+        (Value::String(_), Value::String(_))
+        | (Value::Integer(_), Value::Integer(_))
+        | (Value::Float(_), Value::Float(_))
+        | (Value::Boolean(_), Value::Boolean(_)) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+// строка из окружения или --set к типу default
+fn parse_value(text: &str, default: &Value) -> Option<Value> {
+    let text = text.trim();
+    match default {
+        Value::String(_) => Some(Value::String(text.to_string())),
+        Value::Integer(_) => text.parse().ok().map(Value::Integer),
+        Value::Float(_) => text.parse().ok().map(Value::Float),
+        Value::Boolean(_) => text.parse().ok().map(Value::Boolean),
+        _ => None,
+    }
+}
 
-    let ping_interval: u16 = env::var("ping_interval")
-        .expect("PING_INTERVAL must be set")
-        .parse()
-        .expect("PING_INTERVAL must be a valid u16 number");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
-    let reader_count: u8 = env::var("reader_count")
-        .expect("READER_COUNT must be set")
-        .parse()
-        .expect("READER_COUNT must be a valid u8 number");
+    // каждый файл конфигурации теста - свой, тесты идут параллельно
+    fn args(file: Option<&str>, overrides: &[&str]) -> ConfigArgs {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let config = file.map(|text| {
+            let number = NEXT.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!(
+                "ws-config-{}-{}.toml",
+                std::process::id(),
+                number
+            ));
+            fs::write(&path, text).unwrap();
+            path
+        });
+        ConfigArgs {
+            config,
+            overrides: overrides.iter().map(|item| item.to_string()).collect(),
+            print_config: false,
+        }
+    }
 
-    let port_str = env::var("http_port").unwrap_or("8080".to_string());
-    let http_port: u16 = port_str.parse().unwrap_or(8080);
+    fn env(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
 
-    let wss_url = env::var("wss_url").expect("URL must be set");
+    // http.port во всех слоях: побеждает последний, источник записан
+    #[test]
+    fn later_layers_override_earlier() {
+        let file = Some("[http]\nport = 9001\n[brain]\nfee_rate = 0.001\n");
+        let cases = [
+            (args(None, &[]), env(&[]), 8080, Source::Default),
+            (
+                args(file, &[]),
+                env(&[]),
+                9001,
+                Source::File(PathBuf::new()),
+            ),
+            (
+                args(file, &[]),
+                env(&[("http_port", "9002")]),
+                9002,
+                Source::Env("http_port".to_string()),
+            ),
+            (
+                args(file, &["http.port=9003"]),
+                env(&[("http_port", "9002")]),
+                9003,
+                Source::Cli,
+            ),
+        ];
+        for (args, env_vars, port, source) in cases {
+            let config = load(&args, &env_vars, &[]).unwrap();
+            assert_eq!(config.http.port, port);
+            match (config.source("http.port"), &source) {
+                (Source::File(path), Source::File(_)) => {
+                    assert_eq!(Some(path), args.config.as_ref())
+                }
+                (actual, expected) => assert_eq!(actual, expected),
+            }
+            // ключ, которого нет в верхних слоях, берется из нижнего
+            let fee = if args.config.is_some() { 0.001 } else { 0.0 };
+            assert_eq!(config.brain.fee_rate, fee);
+        }
+    }
 
-    let brain = env::var("brain").expect("BRAIN must be set");
+    #[test]
+    fn bad_values_are_reported_with_key_and_layer() {
+        let file = Some("[http]\nport = \"x\"\n[brain]\nunknown = 1\n");
+        let args = args(
+            file,
+            &[
+                "brain.fee_rate=1.5",
+                "brain.signal_top_k=many",
+                "sinks.nope=1",
+                "oops",
+            ],
+        );
+        let path = args.config.clone().unwrap();
+        let env_vars = env(&[("http_port", "70000")]);
+        let Err(Error::Config(problems)) = load(&args, &env_vars, &["ws.url"]) else {
+            panic!("bad values are accepted");
+        };
+        let file = path.display();
+        for expected in [
+            format!("http.port: file {}: expected integer, got \"x\"", file),
+            format!("brain.unknown: file {}: unknown key", file),
+            "brain.signal_top_k: cli --set: expected integer, got \"many\"".to_string(),
+            "sinks.nope: cli --set: unknown key".to_string(),
+            "--set \"oops\": expected KEY=VALUE".to_string(),
+            "brain.fee_rate: 1.5 is not in [0, 1)".to_string(),
+            "ws.url: must be set (env wss_url)".to_string(),
+        ] {
+            assert!(
+                problems.contains(&expected),
+                "{:?} not in {:#?}",
+                expected,
+                problems
+            );
+        }
+        assert!(
+            problems
+                .iter()
+                .any(|problem| problem.starts_with("http.port: env http_port: ")),
+            "{:#?}",
+            problems
+        );
+    }
 
-    let volume_accept_str = env::var("volume_accept").unwrap_or("false".to_string());
-    let volume_accept: bool = volume_accept_str.parse().unwrap_or(false);
+    #[test]
+    fn out_of_range_values_are_rejected_not_replaced() {
+        let rejected = args(
+            None,
+            &[
+                "brain.calc_scale=12",
+                "brain.earn_scale=11",
+                "brain.capital=-1",
+                "brain.response_rate=-0.5",
+                "brain.signal_min_change=-0.1",
+                "brain.max_price_age_ms=-5",
+            ],
+        );
+        let Err(Error::Config(problems)) = load(&rejected, &[], &[]) else {
+            panic!("out of range values are accepted");
+        };
+        for expected in [
+            "brain.calc_scale: 12 is more than 10 digits of the fixed-point calculation",
+            "brain.earn_scale: 11 is more than 10 digits of the fixed-point calculation",
+            "brain.capital: -1 must be 0 or more",
+            "brain.response_rate: -0.5 must be 0 or more",
+            "brain.signal_min_change: -0.1 must be 0 or more",
+        ] {
+            assert!(
+                problems.iter().any(|problem| problem == expected),
+                "{:?} not in {:#?}",
+                expected,
+                problems
+            );
+        }
+        assert!(
+            problems
+                .iter()
+                .any(|problem| problem.starts_with("brain.max_price_age_ms: cli --set: ")),
+            "{:#?}",
+            problems
+        );
+        assert_eq!(problems.len(), 6, "{:#?}", problems);
 
-    let auto_subscription_str = env::var("auto_subscription").unwrap_or("false".to_string());
-    let auto_subscription: bool = auto_subscription_str.parse().unwrap_or(false);
+        let config = load(&args(None, &["brain.calc_scale=10"]), &[], &[]).unwrap();
+        assert_eq!(config.brain_config().calc_scale, fixed::SCALE);
+    }
+
+    #[test]
+    fn negative_base_currency_percentage_is_rejected() {
+        let settings =
+            std::env::temp_dir().join(format!("ws-settings-{}-negative.ini", std::process::id()));
+        fs::write(
+            &settings,
+            "[> BaseCurrency >]\nUSDT -10%\nBTC 50%\n[< BaseCurrency <]\n\
+             [> AltCurrency >]\nETH\n[< AltCurrency <]\n",
+        )
+        .unwrap();
+        let settings = settings.display().to_string();
+        let rejected = args(None, &[&format!("brain.settings={}", settings)]);
+        let Err(Error::Config(problems)) = load(&rejected, &[], &[]) else {
+            panic!("negative percentage is accepted");
+        };
+        assert_eq!(
+            problems,
+            [format!(
+                "brain.settings: {}: BaseCurrency USDT percentage -10% must be 0 or more",
+                settings
+            )]
+        );
+    }
 
-    let response_rate_str = env::var("response_rate").unwrap_or("1.0".to_string());
-    let response_rate: f64 = response_rate_str.parse().unwrap_or(1.0);
+    #[test]
+    fn exchange_info_is_read_once_and_shared() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/exchange_info/exchange_info.json"
+        );
+        let config = load(
+            &args(None, &[&format!("brain.exchange_info={}", path)]),
+            &[],
+            &[],
+        )
+        .unwrap();
+        let symbols = config.symbols.as_ref().expect("registry is loaded");
+        let brain = config.brain_config();
+        assert!(Arc::ptr_eq(symbols, brain.symbols.as_ref().unwrap()));
 
-    Config {
-        tracing_on,
-        ping_interval,
-        reader_count,
-        http_port,
-        wss_url,
-        brain,
-        volume_accept,
-        auto_subscription,
-        response_rate,
+        let args = args(
+            None,
+            &["brain.exchange_info=/nonexistent/exchange_info.json"],
+        );
+        let Err(Error::Config(problems)) = load(&args, &[], &[]) else {
+            panic!("missing exchange info is accepted");
+        };
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("brain.exchange_info: /nonexistent/exchange_info.json: "));
     }
 }
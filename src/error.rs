@@ -0,0 +1,95 @@
+/*
+Ошибки крейта.
+
+Все, что может пойти не так при запуске: файлы настроек, переменные
+окружения, адрес WS, граф валют, запросы к работающему экземпляру.
+Публичные конструкторы возвращают Result<_, Error> вместо паники, а
+проблемы конфигурации собираются в один отчет Error::Config, чтобы при
+старте увидеть их все сразу.
+*/
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Settings {
+        path: PathBuf,
+        line: usize, // с единицы
+        message: String,
+    },
+    Config(Vec<String>), // все проблемы конфигурации разом
+    Url {
+        url: String,
+        source: url::ParseError,
+    },
+    UnknownAsset(String), // валюты нет в графе
+    Http {
+        url: String,
+        message: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io<P: AsRef<Path>>(path: P, source: io::Error) -> Self {
+        Error::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn settings<P: AsRef<Path>>(path: P, line: usize, message: impl Into<String>) -> Self {
+        Error::Settings {
+            path: path.as_ref().to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+
+    // проблемы конфигурации строками отчета; у одиночных ошибок - одна строка
+    pub fn problems(&self) -> Vec<String> {
+        match self {
+            Error::Config(problems) => problems.clone(),
+            other => vec![other.to_string()],
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Settings {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            Error::Config(problems) => {
+                write!(f, "{} configuration problem(s):", problems.len())?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+            Error::Url { url, source } => write!(f, "invalid url {:?}: {}", url, source),
+            Error::UnknownAsset(symbol) => write!(f, "asset {} is not in the graph", symbol),
+            Error::Http { url, message } => write!(f, "{}: {}", url, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Url { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
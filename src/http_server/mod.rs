@@ -10,11 +10,16 @@ http.send_message поставит сообщение в очередь TwoWayQu
 
 Ключевые команды обмена, будут использовать ту же очередь, но в другом месте...
 */
+use crate::brain::export::{self, ExportFormat};
+use crate::brain::status;
+use crate::latency;
+use crate::logging;
 use crate::queue::TwoWayQueue;
 use crate::websocket_client::WebSocketClient;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tracing::{debug, error};
+use tracing::{debug, error, info};
 use warp::http::StatusCode;
 use warp::Filter;
 
@@ -31,7 +36,7 @@ pub struct HttpServerConfig {
 
 impl HttpServer {
     pub async fn start(config: HttpServerConfig) -> Self {
-        println!("Создаем сервер HTTP");
+        info!("starting HTTP server on port {}", config.port);
 
         let client = config.client.clone();
         let queue = config.incoming_queue.clone();
@@ -40,7 +45,7 @@ impl HttpServer {
         let send_message_filter = warp::path("send_message")
             .and(warp::post())
             .and(with_queue(queue.clone()))
-            .and(warp::body::content_length_limit(1024 * 1))
+            .and(warp::body::content_length_limit(1024))
             .and(warp::body::bytes())
             .and_then(send_message);
         //запрос stop
@@ -49,7 +54,53 @@ impl HttpServer {
             .and(with_client(client.clone()))
             .and_then(stop_websocket);
 
-        let routes = send_message_filter.or(stop_filter);
+        //запрос status - состояние движка в JSON
+        let status_filter = warp::path("status")
+            .and(warp::get())
+            .map(|| warp::reply::json(&status::status()));
+        //запрос metrics - то же в формате Prometheus
+        let metrics_filter = warp::path("metrics").and(warp::get()).map(|| {
+            warp::reply::with_header(
+                status::status().to_prometheus(),
+                "content-type",
+                "text/plain; version=0.0.4",
+            )
+        });
+
+        //запрос graph - граф валют и циклы: ?format=dot|json, ?earn=true - с earn циклов
+        let graph_filter = warp::path("graph")
+            .and(warp::get())
+            .and(warp::query::<HashMap<String, String>>())
+            .map(graph_reply);
+
+        //запрос latency: GET - задержки по стадиям (HDR, мкс), DELETE - сбросить гистограммы
+        let latency_filter = warp::path("latency")
+            .and(warp::get())
+            .map(|| warp::reply::json(&latency::summary()));
+        let latency_reset = warp::path("latency").and(warp::delete()).map(|| {
+            latency::reset();
+            StatusCode::NO_CONTENT
+        });
+
+        //запрос log/filter: GET - текущие директивы журнала, PUT - заменить (тело - директивы EnvFilter)
+        let log_filter_get = warp::path!("log" / "filter")
+            .and(warp::get())
+            .map(|| logging::filter().unwrap_or_default());
+        let log_filter_put = warp::path!("log" / "filter")
+            .and(warp::put())
+            .and(warp::body::content_length_limit(1024))
+            .and(warp::body::bytes())
+            .map(set_log_filter);
+
+        let routes = send_message_filter
+            .or(stop_filter)
+            .or(status_filter)
+            .or(metrics_filter)
+            .or(graph_filter)
+            .or(latency_filter)
+            .or(latency_reset)
+            .or(log_filter_get)
+            .or(log_filter_put);
         let addr = SocketAddr::from(([127, 0, 0, 1], config.port));
         let server = warp::serve(routes).run(addr);
         let server_handle = tokio::spawn(server);
@@ -105,6 +156,39 @@ pub async fn send_message(
     }
 }
 
+fn graph_reply(query: HashMap<String, String>) -> warp::reply::Response {
+    use warp::Reply;
+
+    let format = query.get("format").map_or("json", String::as_str);
+    let Some(format) = ExportFormat::parse(format) else {
+        return warp::reply::with_status("Unknown format", StatusCode::BAD_REQUEST).into_response();
+    };
+    let with_earn = query.get("earn").is_some_and(|earn| earn == "true");
+    match export::published(with_earn) {
+        Some(graph) => {
+            warp::reply::with_header(graph.render(format), "content-type", format.content_type())
+                .into_response()
+        }
+        None => warp::reply::with_status("Graph is not built yet", StatusCode::NOT_FOUND)
+            .into_response(),
+    }
+}
+
+fn set_log_filter(body: bytes::Bytes) -> warp::reply::Response {
+    use warp::Reply;
+
+    let Ok(directives) = std::str::from_utf8(&body) else {
+        return warp::reply::with_status("Invalid filter", StatusCode::BAD_REQUEST).into_response();
+    };
+    match logging::set_filter(directives) {
+        Ok(()) => {
+            info!("log filter set to {:?}", directives.trim());
+            warp::reply::with_status(directives.trim().to_string(), StatusCode::OK).into_response()
+        }
+        Err(err) => warp::reply::with_status(err, StatusCode::BAD_REQUEST).into_response(),
+    }
+}
+
 async fn stop_websocket(client: Arc<WebSocketClient>) -> Result<impl warp::Reply, warp::Rejection> {
     debug!("Stop WebSocket.........");
     match client.close() {
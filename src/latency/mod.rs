@@ -0,0 +1,187 @@
+/*
+Задержки по стадиям: от события на бирже до записи сигнала в UDS.
+
+Trace едет вместе с обновлением цены и сигналом и собирает метки
+монотонных часов процесса (мкс от старта, now_us) на каждой стадии:
+
+    exchange    время события биржи -> получение кадра (настенные часы биржи
+                и машины, без поправки на их расхождение: если часы биржи
+                спешат, задержка считается нулевой)
+    queue       получение кадра (on_text кладет его в очередь) -> читатель забрал
+    parse       разбор кадра в PriceUpdate
+    dispatch    рассылка Observable -> наблюдатель движка начал обработку
+    calculate   расчет треугольников, отбор топа
+    emit        передача пакета в UdsWriter -> запись в сокет завершена
+    total       получение кадра -> запись в сокет
+
+Метка 0 - стадия не пройдена (например, в replay нет очереди WS), такие
+стадии не учитываются. Гистограммы HDR копятся по сигналам, дошедшим до
+сокета, и отдаются HTTP /latency.
+*/
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const MAX_MICROS: u64 = 60_000_000; // все, что дольше минуты, считается минутой
+const SIGNIFICANT_DIGITS: u8 = 3;
+
+lazy_static::lazy_static! {
+    static ref START: Instant = Instant::now();
+    static ref HISTOGRAMS: Mutex<Vec<Histogram<u64>>> = Mutex::new(
+        Stage::ALL.iter().map(|_| new_histogram()).collect()
+    );
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Exchange,
+    Queue,
+    Parse,
+    Dispatch,
+    Calculate,
+    Emit,
+    Total,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 7] = [
+        Stage::Exchange,
+        Stage::Queue,
+        Stage::Parse,
+        Stage::Dispatch,
+        Stage::Calculate,
+        Stage::Emit,
+        Stage::Total,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Exchange => "exchange",
+            Stage::Queue => "queue",
+            Stage::Parse => "parse",
+            Stage::Dispatch => "dispatch",
+            Stage::Calculate => "calculate",
+            Stage::Emit => "emit",
+            Stage::Total => "total",
+        }
+    }
+}
+
+// монотонное время процесса, мкс; 0 не выдается, он значит "нет метки"
+pub fn now_us() -> i64 {
+    START.elapsed().as_micros() as i64 + 1
+}
+
+// настенное время, мкс: им помечается recv_ts, по нему же /status судит о возрасте цен
+pub fn wall_us() -> i64 {
+    chrono::Utc::now().timestamp_micros()
+}
+
+// метка получения кадра: монотонная для стадий и настенная для recv_ts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stamp {
+    pub mono_us: i64,
+    pub wall_us: i64,
+}
+
+impl Stamp {
+    pub fn now() -> Self {
+        Stamp {
+            mono_us: now_us(),
+            wall_us: wall_us(),
+        }
+    }
+
+    // задержка от события биржи (мс, ее часы) до получения кадра, мкс; не меньше 0
+    pub fn exchange_delay(&self, exchange_ts: i64) -> i64 {
+        (self.wall_us - exchange_ts * 1000).max(0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Trace {
+    pub exchange_us: Option<i64>, // задержка биржа -> получение, мкс; None - биржа время не прислала
+    pub received: i64,
+    pub dequeued: i64,
+    pub parsed: i64,
+    pub observed: i64,
+    pub calculated: i64,
+    pub written: i64,
+}
+
+impl Trace {
+    // длительность стадии, мкс; None - одна из меток не проставлена
+    pub fn stage(&self, stage: Stage) -> Option<i64> {
+        let span = |from: i64, to: i64| (from > 0 && to > 0).then(|| (to - from).max(0));
+        match stage {
+            Stage::Exchange => self.exchange_us,
+            Stage::Queue => span(self.received, self.dequeued),
+            Stage::Parse => span(self.dequeued, self.parsed),
+            Stage::Dispatch => span(self.parsed, self.observed),
+            Stage::Calculate => span(self.observed, self.calculated),
+            Stage::Emit => span(self.calculated, self.written),
+            Stage::Total => span(self.received, self.written),
+        }
+    }
+}
+
+// учесть пройденные стадии сигнала в гистограммах
+pub fn record(trace: &Trace) {
+    let mut histograms = HISTOGRAMS.lock().unwrap();
+    for (index, stage) in Stage::ALL.iter().enumerate() {
+        if let Some(micros) = trace.stage(*stage) {
+            histograms[index].saturating_record((micros as u64).clamp(1, MAX_MICROS));
+        }
+    }
+}
+
+pub fn reset() {
+    for histogram in HISTOGRAMS.lock().unwrap().iter_mut() {
+        histogram.reset();
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StageSummary {
+    pub count: u64,
+    pub min: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+// сводка по стадиям в мкс, ключ - имя стадии
+pub fn summary() -> BTreeMap<&'static str, StageSummary> {
+    let histograms = HISTOGRAMS.lock().unwrap();
+    Stage::ALL
+        .iter()
+        .zip(histograms.iter())
+        .map(|(stage, histogram)| {
+            let summary = if histogram.is_empty() {
+                StageSummary::default()
+            } else {
+                StageSummary {
+                    count: histogram.len(),
+                    min: histogram.min(),
+                    mean: histogram.mean(),
+                    p50: histogram.value_at_quantile(0.5),
+                    p90: histogram.value_at_quantile(0.9),
+                    p99: histogram.value_at_quantile(0.99),
+                    p999: histogram.value_at_quantile(0.999),
+                    max: histogram.max(),
+                }
+            };
+            (stage.as_str(), summary)
+        })
+        .collect()
+}
+
+fn new_histogram() -> Histogram<u64> {
+    Histogram::new_with_bounds(1, MAX_MICROS, SIGNIFICANT_DIGITS)
+        .expect("static histogram bounds are valid")
+}
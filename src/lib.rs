@@ -0,0 +1,17 @@
+pub mod brain;
+pub mod brain_sets;
+pub mod cli;
+pub mod config;
+pub mod error;
+pub mod http_server;
+pub mod latency;
+pub mod logging;
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock_exchange;
+pub mod queue;
+pub mod recorder;
+pub mod replay;
+pub mod stack;
+pub mod symbols;
+pub mod uds_write;
+pub mod websocket_client;
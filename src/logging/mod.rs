@@ -0,0 +1,93 @@
+/*
+Журналирование через tracing.
+
+Фильтр - директивы EnvFilter: "info", "ws::brain=debug,ws::recorder=warn",
+"signals=info". Его можно заменить на лету через set_filter (HTTP PUT
+/log/filter), формат и тайминг спанов задаются один раз при запуске.
+
+Формат text - обычные строки, json - объект на строку для сборщиков логов.
+С span_timing при закрытии каждого включенного фильтром спана пишется его
+время (time.busy / time.idle), например react_to_update на уровне debug.
+*/
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+use crate::config::LogSection;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+struct FilterControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    current: Mutex<String>,
+}
+
+static FILTER: OnceCell<FilterControl> = OnceCell::new();
+
+// директивы EnvFilter; ошибка - текст для отчета
+pub fn parse_filter(directives: &str) -> Result<EnvFilter, String> {
+    EnvFilter::try_new(directives.trim()).map_err(|err| err.to_string())
+}
+
+/*
+Устанавливает глобальный подписчик. Повторный вызов (подписчик уже есть,
+например в тестах) ничего не делает. Битый фильтр заменяется на "info",
+до сюда он доходит только в обход проверки конфигурации.
+*/
+pub fn init(config: &LogSection) {
+    let (filter, directives) = match parse_filter(&config.filter) {
+        Ok(filter) => (filter, config.filter.trim().to_string()),
+        Err(_) => (EnvFilter::new("info"), "info".to_string()),
+    };
+    let (filter, handle) = reload::Layer::new(filter);
+    let spans = if config.span_timing {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+    let (text, json) = match config.format {
+        LogFormat::Text => (Some(fmt::layer().with_span_events(spans)), None),
+        LogFormat::Json => (None, Some(fmt::layer().json().with_span_events(spans))),
+    };
+
+    if tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .try_init()
+        .is_ok()
+    {
+        let _ = FILTER.set(FilterControl {
+            handle,
+            current: Mutex::new(directives),
+        });
+    }
+}
+
+// заменить фильтр работающего процесса
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    let control = FILTER.get().ok_or("logging is not initialized")?;
+    let filter = parse_filter(directives)?;
+    control
+        .handle
+        .reload(filter)
+        .map_err(|err| err.to_string())?;
+    *control.current.lock().unwrap() = directives.trim().to_string();
+    Ok(())
+}
+
+// текущие директивы; None - журналирование не инициализировано через init
+pub fn filter() -> Option<String> {
+    FILTER
+        .get()
+        .map(|control| control.current.lock().unwrap().clone())
+}
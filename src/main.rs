@@ -0,0 +1,14 @@
+use clap::Parser;
+use std::process::ExitCode;
+
+use ws::cli::{self, Cli};
+
+fn main() -> ExitCode {
+    match cli::execute(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,109 @@
+/*
+Источники тиков мок-биржи.
+
+    Scripted    заданные тики по порядку, по одному на tick_interval;
+                с repeat сценарий крутится по кругу
+    RandomWalk  случайное блуждание середины цены по парам, на каждом
+                интервале по тику на пару; seed делает ряд воспроизводимым
+    Manual      тиков нет, их шлет тест через MockExchange::send_tick
+*/
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+
+// тик bookTicker одной пары; цены и объемы строками, как у биржи
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockTick {
+    pub symbol: String,
+    pub bid: String,
+    pub ask: String,
+    pub bid_qty: String,
+    pub ask_qty: String,
+}
+
+impl MockTick {
+    pub fn new(symbol: &str, bid: &str, ask: &str) -> Self {
+        MockTick {
+            symbol: symbol.to_ascii_uppercase(),
+            bid: bid.to_string(),
+            ask: ask.to_string(),
+            bid_qty: "1".to_string(),
+            ask_qty: "1".to_string(),
+        }
+    }
+
+    // кадр одиночного потока bookTicker; event_time - время события, мс
+    pub fn to_frame(&self, update_id: u64, event_time: i64) -> String {
+        json!({
+            "u": update_id,
+            "s": self.symbol,
+            "b": self.bid,
+            "B": self.bid_qty,
+            "a": self.ask,
+            "A": self.ask_qty,
+            "E": event_time,
+        })
+        .to_string()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Feed {
+    Scripted { ticks: Vec<MockTick>, repeat: bool },
+    RandomWalk(RandomWalk),
+    Manual,
+}
+
+#[derive(Clone, Debug)]
+pub struct RandomWalk {
+    pub pairs: Vec<(String, f64)>, // символ и начальная середина цены
+    pub step_bps: f64,             // наибольший шаг середины за тик
+    pub spread_bps: f64,           // спред ask - bid от середины
+    pub seed: u64,
+}
+
+impl RandomWalk {
+    pub fn new(pairs: &[(&str, f64)]) -> Self {
+        RandomWalk {
+            pairs: pairs
+                .iter()
+                .map(|(symbol, mid)| (symbol.to_ascii_uppercase(), *mid))
+                .collect(),
+            step_bps: 5.0,
+            spread_bps: 2.0,
+            seed: 0,
+        }
+    }
+
+    pub fn walker(&self) -> Walker {
+        Walker {
+            walk: self.clone(),
+            rng: StdRng::seed_from_u64(self.seed),
+        }
+    }
+}
+
+pub struct Walker {
+    walk: RandomWalk,
+    rng: StdRng,
+}
+
+impl Walker {
+    // следующий шаг: по тику на каждую пару
+    pub fn step(&mut self) -> Vec<MockTick> {
+        let step = self.walk.step_bps / 10_000.0;
+        let half_spread = self.walk.spread_bps / 20_000.0;
+        let mut ticks = Vec::with_capacity(self.walk.pairs.len());
+        for (symbol, mid) in self.walk.pairs.iter_mut() {
+            *mid *= 1.0 + self.rng.gen_range(-step..=step);
+            ticks.push(MockTick {
+                symbol: symbol.clone(),
+                bid: format!("{:.8}", *mid * (1.0 - half_spread)),
+                ask: format!("{:.8}", *mid * (1.0 + half_spread)),
+                bid_qty: format!("{:.4}", self.rng.gen_range(0.1..100.0)),
+                ask_qty: format!("{:.4}", self.rng.gen_range(0.1..100.0)),
+            });
+        }
+        ticks
+    }
+}
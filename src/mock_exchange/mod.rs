@@ -0,0 +1,407 @@
+/*
+Мок биржи для интеграционных тестов: WS-сервер в процессе (warp ws).
+Собирается только в тестах и с feature test-support.
+
+Слушает 127.0.0.1 на свободном порту, путь любой (url() отдает .../ws).
+Понимает SUBSCRIBE, UNSUBSCRIBE и LIST_SUBSCRIPTIONS в формате Binance для
+потоков <symbol>@bookTicker и шлет кадры bookTicker только по подписанным
+символам. Источник тиков (feed) начинает работу с первой подписки, чтобы
+сценарий не ушел в пустоту до подключения клиента.
+
+Сбои:
+    disconnect_after  закрыть соединение после N кадров тиков
+    disconnect_all    оборвать все соединения прямо сейчас
+    frame_delay       пауза перед каждым кадром (медленная сеть)
+    slow_consumer     соединение отстало от потока больше чем на buffer
+                      тиков: Skip - пропустить их, Disconnect - закрыть
+                      соединение кодом 1008, как делает биржа
+
+На ping клиента отвечает pong (это делает tungstenite), с ping_interval
+сам шлет ping. Счетчики всего этого - stats().
+*/
+pub mod feed;
+
+use futures_util::{SinkExt, StreamExt};
+use std::collections::BTreeSet;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Interval};
+use tracing::debug;
+use warp::ws::{Message, WebSocket};
+use warp::Filter;
+
+pub use feed::{Feed, MockTick, RandomWalk};
+
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_POLICY: u16 = 1008;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlowConsumer {
+    Skip,
+    Disconnect,
+}
+
+#[derive(Clone, Debug)]
+pub struct MockExchangeConfig {
+    pub feed: Feed,
+    pub tick_interval: Duration, // не меньше 1 мс
+    pub frame_delay: Duration,
+    pub disconnect_after: Option<usize>, // кадров тиков на соединение
+    pub buffer: usize,                   // тиков, на которые соединение может отстать
+    pub slow_consumer: SlowConsumer,
+    pub ping_interval: Option<Duration>,
+}
+
+impl Default for MockExchangeConfig {
+    fn default() -> Self {
+        MockExchangeConfig {
+            feed: Feed::Manual,
+            tick_interval: Duration::from_millis(10),
+            frame_delay: Duration::ZERO,
+            disconnect_after: None,
+            buffer: 1024,
+            slow_consumer: SlowConsumer::Skip,
+            ping_interval: None,
+        }
+    }
+}
+
+// счетчики мок-биржи на момент вызова stats()
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MockStats {
+    pub connections: u64,   // принято соединений за все время
+    pub active: u64,        // открыто сейчас
+    pub requests: u64,      // текстовых запросов от клиентов
+    pub subscriptions: u64, // подписано потоков за все время
+    pub frames_sent: u64,
+    pub frames_dropped: u64, // пропущено из-за отставания соединения
+    pub disconnects: u64,    // соединений, закрытых мок-биржей
+    pub slow_consumers: u64, // из них за отставание
+    pub pings: u64,          // ping от клиентов
+    pub pongs: u64,          // pong на ping мок-биржи
+}
+
+#[derive(Default)]
+struct Counters {
+    connections: AtomicU64,
+    active: AtomicU64,
+    requests: AtomicU64,
+    subscriptions: AtomicU64,
+    frames_sent: AtomicU64,
+    frames_dropped: AtomicU64,
+    disconnects: AtomicU64,
+    slow_consumers: AtomicU64,
+    pings: AtomicU64,
+    pongs: AtomicU64,
+}
+
+impl Counters {
+    fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> MockStats {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MockStats {
+            connections: get(&self.connections),
+            active: get(&self.active),
+            requests: get(&self.requests),
+            subscriptions: get(&self.subscriptions),
+            frames_sent: get(&self.frames_sent),
+            frames_dropped: get(&self.frames_dropped),
+            disconnects: get(&self.disconnects),
+            slow_consumers: get(&self.slow_consumers),
+            pings: get(&self.pings),
+            pongs: get(&self.pongs),
+        }
+    }
+}
+
+struct Shared {
+    config: MockExchangeConfig,
+    ticks: broadcast::Sender<MockTick>,
+    control: broadcast::Sender<()>, // оборвать все соединения
+    subscribed: Notify,             // была хотя бы одна подписка
+    update_id: AtomicU64,
+    counters: Counters,
+}
+
+pub struct MockExchange {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    shutdown: Option<oneshot::Sender<()>>,
+    feed: JoinHandle<()>,
+}
+
+impl MockExchange {
+    // запускает сервер в текущем tokio runtime
+    pub async fn start(config: MockExchangeConfig) -> io::Result<Self> {
+        let (ticks, _) = broadcast::channel(config.buffer.max(1));
+        let (control, _) = broadcast::channel(1);
+        let shared = Arc::new(Shared {
+            config,
+            ticks,
+            control,
+            subscribed: Notify::new(),
+            update_id: AtomicU64::new(0),
+            counters: Counters::default(),
+        });
+
+        let with_shared = {
+            let shared = Arc::clone(&shared);
+            warp::any().map(move || Arc::clone(&shared))
+        };
+        let routes = warp::ws()
+            .and(with_shared)
+            .map(|ws: warp::ws::Ws, shared: Arc<Shared>| {
+                ws.on_upgrade(move |socket| serve_connection(socket, shared))
+            });
+
+        let (shutdown, stop) = oneshot::channel::<()>();
+        let (addr, server) = warp::serve(routes)
+            .try_bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
+                let _ = stop.await;
+            })
+            .map_err(io::Error::other)?;
+        tokio::spawn(server);
+        let feed = tokio::spawn(run_feed(Arc::clone(&shared)));
+        debug!("mock exchange listening on {}", addr);
+
+        Ok(MockExchange {
+            addr,
+            shared,
+            shutdown: Some(shutdown),
+            feed,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}/ws", self.addr)
+    }
+
+    // разослать тик всем соединениям; возвращает число соединений
+    pub fn send_tick(&self, tick: MockTick) -> usize {
+        self.shared.ticks.send(tick).unwrap_or(0)
+    }
+
+    pub fn disconnect_all(&self) {
+        let _ = self.shared.control.send(());
+    }
+
+    pub fn stats(&self) -> MockStats {
+        self.shared.counters.snapshot()
+    }
+}
+
+impl Drop for MockExchange {
+    fn drop(&mut self) {
+        self.feed.abort();
+        self.disconnect_all();
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn run_feed(shared: Arc<Shared>) {
+    let send = |tick: MockTick| {
+        let _ = shared.ticks.send(tick);
+    };
+    let mut timer = time::interval(shared.config.tick_interval.max(Duration::from_millis(1)));
+    match shared.config.feed.clone() {
+        Feed::Manual => {}
+        Feed::Scripted { ticks, repeat } => {
+            shared.subscribed.notified().await;
+            loop {
+                for tick in ticks.iter().cloned() {
+                    timer.tick().await;
+                    send(tick);
+                }
+                if !repeat || ticks.is_empty() {
+                    break;
+                }
+            }
+        }
+        Feed::RandomWalk(walk) => {
+            shared.subscribed.notified().await;
+            let mut walker = walk.walker();
+            loop {
+                timer.tick().await;
+                walker.step().into_iter().for_each(send);
+            }
+        }
+    }
+}
+
+async fn serve_connection(socket: WebSocket, shared: Arc<Shared>) {
+    let counters = &shared.counters;
+    Counters::add(&counters.connections, 1);
+    Counters::add(&counters.active, 1);
+
+    let (mut tx, mut rx) = socket.split();
+    let mut ticks = shared.ticks.subscribe();
+    let mut control = shared.control.subscribe();
+    let mut ping = shared.config.ping_interval.map(|period| {
+        let start = time::Instant::now() + period;
+        time::interval_at(start, period)
+    });
+    let mut subscriptions: BTreeSet<String> = BTreeSet::new();
+    let mut sent = 0usize;
+
+    // причина закрытия мок-биржей; None - клиент ушел сам
+    let close: Option<(u16, &str)> = loop {
+        tokio::select! {
+            message = rx.next() => {
+                let message = match message {
+                    Some(Ok(message)) => message,
+                    _ => break None,
+                };
+                if message.is_ping() {
+                    Counters::add(&counters.pings, 1);
+                } else if message.is_pong() {
+                    Counters::add(&counters.pongs, 1);
+                } else if message.is_close() {
+                    break None;
+                } else if let Ok(text) = message.to_str() {
+                    Counters::add(&counters.requests, 1);
+                    let (reply, added) = handle_request(text, &mut subscriptions);
+                    if added > 0 {
+                        Counters::add(&counters.subscriptions, added as u64);
+                        shared.subscribed.notify_one();
+                    }
+                    if tx.send(Message::text(reply)).await.is_err() {
+                        break None;
+                    }
+                }
+            }
+            tick = ticks.recv() => match tick {
+                Ok(tick) => {
+                    if !subscriptions.contains(&tick.symbol) {
+                        continue;
+                    }
+                    if !shared.config.frame_delay.is_zero() {
+                        time::sleep(shared.config.frame_delay).await;
+                    }
+                    let update_id = shared.update_id.fetch_add(1, Ordering::Relaxed) + 1;
+                    let frame = tick.to_frame(update_id, chrono::Utc::now().timestamp_millis());
+                    if tx.send(Message::text(frame)).await.is_err() {
+                        break None;
+                    }
+                    Counters::add(&counters.frames_sent, 1);
+                    sent += 1;
+                    if shared.config.disconnect_after == Some(sent) {
+                        break Some((CLOSE_NORMAL, "scripted disconnect"));
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    Counters::add(&counters.frames_dropped, skipped);
+                    if shared.config.slow_consumer == SlowConsumer::Disconnect {
+                        Counters::add(&counters.slow_consumers, 1);
+                        break Some((CLOSE_POLICY, "slow consumer"));
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => break None,
+            },
+            _ = control.recv() => break Some((CLOSE_NORMAL, "disconnect")),
+            _ = next_ping(&mut ping) => {
+                if tx.send(Message::ping(Vec::new())).await.is_err() {
+                    break None;
+                }
+            }
+        }
+    };
+
+    if let Some((code, reason)) = close {
+        debug!("mock exchange closes connection: {}", reason);
+        Counters::add(&counters.disconnects, 1);
+        let _ = tx.send(Message::close_with(code, reason)).await;
+        let _ = tx.close().await;
+    }
+    counters.active.fetch_sub(1, Ordering::Relaxed);
+}
+
+// без ping_interval никогда не срабатывает
+async fn next_ping(ping: &mut Option<Interval>) {
+    match ping {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/*
+Запрос клиента в формате Binance: {"method":"SUBSCRIBE","params":[...],"id":1}.
+Возвращает ответ и число новых подписок.
+*/
+fn handle_request(text: &str, subscriptions: &mut BTreeSet<String>) -> (String, usize) {
+    let error = |id: &serde_json::Value, msg: &str| {
+        serde_json::json!({"error": {"code": 2, "msg": msg}, "id": id}).to_string()
+    };
+    let request: serde_json::Value = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(_) => return (error(&serde_json::Value::Null, "Invalid JSON"), 0),
+    };
+    let id = request.get("id").cloned().unwrap_or_default();
+    let method = request.get("method").and_then(|method| method.as_str());
+    let streams: Option<Vec<&str>> = match request.get("params") {
+        None => Some(Vec::new()),
+        Some(params) => params
+            .as_array()
+            .and_then(|params| params.iter().map(|param| param.as_str()).collect()),
+    };
+    let symbols: Option<Vec<String>> = streams.and_then(|streams| {
+        streams
+            .into_iter()
+            .map(|stream| {
+                stream
+                    .strip_suffix("@bookTicker")
+                    .filter(|symbol| !symbol.is_empty())
+                    .map(str::to_ascii_uppercase)
+            })
+            .collect()
+    });
+    let symbols = match symbols {
+        Some(symbols) => symbols,
+        None => return (error(&id, "Invalid request: unknown stream"), 0),
+    };
+
+    match method {
+        Some("SUBSCRIBE") => {
+            let added = symbols
+                .into_iter()
+                .filter(|symbol| subscriptions.insert(symbol.clone()))
+                .count();
+            (
+                serde_json::json!({"result": null, "id": id}).to_string(),
+                added,
+            )
+        }
+        Some("UNSUBSCRIBE") => {
+            for symbol in &symbols {
+                subscriptions.remove(symbol);
+            }
+            (serde_json::json!({"result": null, "id": id}).to_string(), 0)
+        }
+        Some("LIST_SUBSCRIPTIONS") => {
+            let streams: Vec<String> = subscriptions
+                .iter()
+                .map(|symbol| format!("{}@bookTicker", symbol.to_ascii_lowercase()))
+                .collect();
+            (
+                serde_json::json!({"result": streams, "id": id}).to_string(),
+                0,
+            )
+        }
+        _ => (error(&id, "Invalid request: unknown method"), 0),
+    }
+}
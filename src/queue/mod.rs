@@ -1,38 +1,102 @@
 /*
 Очередь. Потокобезопасный синглтон. Несколько писателей ставят в очередь,
 один читатель принимает сообщения.
+
+Отводы (tap) получают копию каждого сообщения при постановке в очередь,
+не забирая его у читателя. Отвод должен быть быстрым и не блокирующим.
+add_tap отдает TapHandle, по нему отвод снимается через remove_tap.
+
+close будит всех читателей: они дочитывают очередь, после чего pop
+отдает None.
+
+Каждое сообщение при постановке получает метку времени (latency::Stamp):
+pop_stamped отдает ее читателю, чтобы считать задержку в очереди.
  */
 
 use once_cell::sync::Lazy;
 use std::collections::VecDeque;
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+
+use crate::latency::Stamp;
+
+pub type Tap = Box<dyn Fn(&str) + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TapHandle(u64);
 
 pub struct TwoWayQueue {
-    data: Mutex<VecDeque<String>>,
+    data: Mutex<VecDeque<(String, Stamp)>>,
     condvar: Condvar,
+    taps: RwLock<Vec<(TapHandle, Tap)>>,
+    next_tap: AtomicU64,
+    closed: AtomicBool,
+}
+
+impl Default for TwoWayQueue {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TwoWayQueue {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             data: Mutex::new(VecDeque::new()),
             condvar: Condvar::new(),
+            taps: RwLock::new(Vec::new()),
+            next_tap: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
         }
     }
 
     pub fn push(&self, value: String) {
+        // метка до отводов: их работа входит в задержку очереди, а не биржи
+        let stamp = Stamp::now();
+        for (_, tap) in self.taps.read().unwrap().iter() {
+            tap(&value);
+        }
         let mut queue = self.data.lock().unwrap();
-        queue.push_front(value);
+        queue.push_front((value, stamp));
         self.condvar.notify_one();
     }
 
+    pub fn add_tap(&self, tap: Tap) -> TapHandle {
+        let handle = TapHandle(self.next_tap.fetch_add(1, Ordering::Relaxed));
+        self.taps.write().unwrap().push((handle, tap));
+        handle
+    }
+
+    // снимает отвод; false - его уже нет
+    pub fn remove_tap(&self, handle: TapHandle) -> bool {
+        let mut taps = self.taps.write().unwrap();
+        let before = taps.len();
+        taps.retain(|(tap, _)| *tap != handle);
+        taps.len() != before
+    }
+
     pub fn pop(&self) -> Option<String> {
+        self.pop_stamped().map(|(value, _)| value)
+    }
+
+    // сообщение вместе с меткой постановки в очередь; None - очередь закрыта и пуста
+    pub fn pop_stamped(&self) -> Option<(String, Stamp)> {
         let mut queue = self.data.lock().unwrap();
         while queue.is_empty() {
+            if self.closed.load(Ordering::SeqCst) {
+                return None;
+            }
             queue = self.condvar.wait(queue).unwrap();
         }
         queue.pop_back()
     }
+
+    pub fn close(&self) {
+        // под замком очереди: читатель не пропустит пробуждение между проверкой и wait
+        let _queue = self.data.lock().unwrap();
+        self.closed.store(true, Ordering::SeqCst);
+        self.condvar.notify_all();
+    }
 }
 
 pub type SharedQueue = Arc<TwoWayQueue>;
@@ -40,3 +104,29 @@ pub type SharedQueue = Arc<TwoWayQueue>;
 pub static INCOMING_QUEUE: Lazy<SharedQueue> = Lazy::new(|| Arc::new(TwoWayQueue::new()));
 
 pub static OUTCOMING_QUEUE: Lazy<SharedQueue> = Lazy::new(|| Arc::new(TwoWayQueue::new()));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn close_lets_readers_drain_and_stop() {
+        let queue = Arc::new(TwoWayQueue::new());
+        let reader = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                let mut read = Vec::new();
+                while let Some(value) = queue.pop() {
+                    read.push(value);
+                }
+                read
+            })
+        };
+        queue.push("a".to_string());
+        queue.push("b".to_string());
+        queue.close();
+        assert_eq!(reader.join().unwrap(), ["a", "b"]);
+        assert_eq!(queue.pop(), None);
+    }
+}
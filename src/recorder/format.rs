@@ -0,0 +1,265 @@
+/*
+Форматы файлов записи рыночных данных.
+
+Binary: заголовок MAGIC, затем записи подряд, все числа little-endian
+    raw:    [1][ts i64][len u32][bytes]
+    update: [2][recv_ts i64][flag u8][exchange_ts i64, если flag == 1]
+            [symbol][bid][ask][bid_qty][ask_qty] - каждая строка [len u16][bytes]
+
+Jsonl: одна запись Record в JSON на строку.
+
+RecordReader сам определяет формат и сжатие по первым байтам файла.
+*/
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use crate::brain::observer::PriceUpdate;
+use crate::latency::Trace;
+
+pub const MAGIC: &[u8; 6] = b"WSREC\x01";
+
+const ZSTD_MAGIC: &[u8; 4] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+const TAG_RAW: u8 = 1;
+const TAG_UPDATE: u8 = 2;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Record {
+    Raw { ts: i64, text: String }, // сырой кадр из WS, ts - время получения, мкс
+    Update(PriceUpdate),
+}
+
+impl Record {
+    pub fn ts(&self) -> i64 {
+        match self {
+            Record::Raw { ts, .. } => *ts,
+            Record::Update(update) => update.recv_ts,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    Binary,
+    Jsonl,
+}
+
+impl RecordFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "bin" | "binary" => Some(RecordFormat::Binary),
+            "jsonl" | "json" => Some(RecordFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordFormat::Binary => "bin",
+            RecordFormat::Jsonl => "jsonl",
+        }
+    }
+
+    // пишется один раз в начало каждого файла
+    pub fn write_header<W: Write>(&self, out: &mut W) -> io::Result<usize> {
+        match self {
+            RecordFormat::Binary => {
+                out.write_all(MAGIC)?;
+                Ok(MAGIC.len())
+            }
+            RecordFormat::Jsonl => Ok(0),
+        }
+    }
+
+    // возвращает количество записанных байт (до сжатия)
+    pub fn write_record<W: Write>(&self, out: &mut W, record: &Record) -> io::Result<usize> {
+        match self {
+            RecordFormat::Binary => write_binary(out, record),
+            RecordFormat::Jsonl => {
+                let mut line = serde_json::to_vec(record)?;
+                line.push(b'\n');
+                out.write_all(&line)?;
+                Ok(line.len())
+            }
+        }
+    }
+}
+
+// в конфигурации формат пишется так же, как расширение файла: "bin" или "jsonl"
+impl Serialize for RecordFormat {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.extension())
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordFormat {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        RecordFormat::parse(&value).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "unknown record format {:?}, expected bin or jsonl",
+                value
+            ))
+        })
+    }
+}
+
+fn write_binary<W: Write>(out: &mut W, record: &Record) -> io::Result<usize> {
+    let mut buf = Vec::with_capacity(64);
+    match record {
+        Record::Raw { ts, text } => {
+            buf.push(TAG_RAW);
+            buf.extend_from_slice(&ts.to_le_bytes());
+            let len = u32::try_from(text.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "raw frame too long"))?;
+            buf.extend_from_slice(&len.to_le_bytes());
+            buf.extend_from_slice(text.as_bytes());
+        }
+        Record::Update(update) => {
+            buf.push(TAG_UPDATE);
+            buf.extend_from_slice(&update.recv_ts.to_le_bytes());
+            match update.exchange_ts {
+                Some(ts) => {
+                    buf.push(1);
+                    buf.extend_from_slice(&ts.to_le_bytes());
+                }
+                None => buf.push(0),
+            }
+            for field in [
+                &update.symbol,
+                &update.bid,
+                &update.ask,
+                &update.bid_qty,
+                &update.ask_qty,
+            ] {
+                put_str(&mut buf, field)?;
+            }
+        }
+    }
+    out.write_all(&buf)?;
+    Ok(buf.len())
+}
+
+fn put_str(buf: &mut Vec<u8>, value: &str) -> io::Result<()> {
+    let len = u16::try_from(value.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "field too long"))?;
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+pub struct RecordReader {
+    input: Box<dyn BufRead + Send>,
+    format: RecordFormat,
+    line: String,
+}
+
+impl RecordReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut input: Box<dyn BufRead + Send> = if file.fill_buf()?.starts_with(ZSTD_MAGIC) {
+            Box::new(BufReader::new(zstd::Decoder::with_buffer(file)?))
+        } else {
+            Box::new(file)
+        };
+
+        let format = if input.fill_buf()?.starts_with(MAGIC) {
+            input.consume(MAGIC.len());
+            RecordFormat::Binary
+        } else {
+            RecordFormat::Jsonl
+        };
+
+        Ok(RecordReader {
+            input,
+            format,
+            line: String::new(),
+        })
+    }
+
+    pub fn format(&self) -> RecordFormat {
+        self.format
+    }
+
+    fn read_binary(&mut self) -> io::Result<Option<Record>> {
+        let mut tag = [0u8; 1];
+        if self.input.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        match tag[0] {
+            TAG_RAW => {
+                let ts = i64::from_le_bytes(read_array(&mut self.input)?);
+                let len = u32::from_le_bytes(read_array(&mut self.input)?) as usize;
+                let text = read_string(&mut self.input, len)?;
+                Ok(Some(Record::Raw { ts, text }))
+            }
+            TAG_UPDATE => {
+                let recv_ts = i64::from_le_bytes(read_array(&mut self.input)?);
+                let [flag] = read_array(&mut self.input)?;
+                let exchange_ts = if flag == 1 {
+                    Some(i64::from_le_bytes(read_array(&mut self.input)?))
+                } else {
+                    None
+                };
+                Ok(Some(Record::Update(PriceUpdate {
+                    symbol: get_str(&mut self.input)?,
+                    bid: get_str(&mut self.input)?,
+                    ask: get_str(&mut self.input)?,
+                    bid_qty: get_str(&mut self.input)?,
+                    ask_qty: get_str(&mut self.input)?,
+                    exchange_ts,
+                    recv_ts,
+                    trace: Trace::default(),
+                })))
+            }
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown record tag {}", other),
+            )),
+        }
+    }
+
+    fn read_jsonl(&mut self) -> io::Result<Option<Record>> {
+        loop {
+            self.line.clear();
+            if self.input.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            if !self.line.trim().is_empty() {
+                return Ok(Some(serde_json::from_str(&self.line)?));
+            }
+        }
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.format {
+            RecordFormat::Binary => self.read_binary(),
+            RecordFormat::Jsonl => self.read_jsonl(),
+        };
+        record.transpose()
+    }
+}
+
+fn read_array<const N: usize, R: Read>(input: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_string<R: Read>(input: &mut R, len: usize) -> io::Result<String> {
+    let mut buf = vec![0u8; len];
+    input.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn get_str<R: Read>(input: &mut R) -> io::Result<String> {
+    let len = u16::from_le_bytes(read_array(input)?) as usize;
+    read_string(input, len)
+}
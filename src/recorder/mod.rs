@@ -0,0 +1,398 @@
+/*
+Запись рыночных данных на диск.
+
+Recorder подключается отводом к OUTCOMING_QUEUE (сырые кадры WS) и
+наблюдателем к Observable (разобранные PriceUpdate). Записи попадают в
+ограниченный канал и пишутся отдельным потоком, горячий путь никогда не
+ждет диска: при переполнении канала запись отбрасывается и считается.
+
+Файлы ротируются по размеру и/или по времени, опционально сжимаются zstd.
+Имя файла - время открытия и порядковый номер: md-<время>-<номер>.<формат>,
+номер не дает совпасть именам файлов, открытых в одну миллисекунду.
+При ротации и остановке файл дописывается явно (finish), ошибки
+завершения zstd-фрейма не теряются.
+*/
+pub mod format;
+
+use chrono::{Local, Utc};
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender, TrySendError};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+use crate::brain::observer::{Observable, ObserverHandle};
+use crate::queue::{TapHandle, TwoWayQueue};
+use format::{Record, RecordFormat};
+
+const CHANNEL_CAPACITY: usize = 65536;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Clone, Debug)]
+pub struct RecorderConfig {
+    pub dir: PathBuf,
+    pub format: RecordFormat,
+    pub rotate_bytes: Option<u64>,      // None - без ротации по размеру
+    pub rotate_every: Option<Duration>, // None - без ротации по времени
+    pub zstd: bool,
+    pub raw: bool,     // писать сырые кадры из очереди
+    pub updates: bool, // писать PriceUpdate из Observable
+}
+
+enum Message {
+    Record(Box<Record>), // в куче: Record с Trace намного больше Stop
+    Stop,
+}
+
+pub struct Recorder {
+    sender: Sender<Message>,
+    dropped: Arc<AtomicU64>,
+    tap: Option<TapHandle>,
+    observer: Option<ObserverHandle>,
+    worker: thread::JoinHandle<()>,
+}
+
+impl Recorder {
+    pub fn start(
+        config: RecorderConfig,
+        queue: &TwoWayQueue,
+        observable: &Observable,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let (sender, receiver) = channel::bounded(CHANNEL_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        let tap = if config.raw {
+            let sender = sender.clone();
+            let dropped = Arc::clone(&dropped);
+            Some(queue.add_tap(Box::new(move |text| {
+                let record = Record::Raw {
+                    ts: Utc::now().timestamp_micros(),
+                    text: text.to_string(),
+                };
+                offer(&sender, &dropped, record);
+            })))
+        } else {
+            None
+        };
+
+        let observer = if config.updates {
+            let sender = sender.clone();
+            let dropped = Arc::clone(&dropped);
+            Some(observable.add_observer(Box::new(move |update| {
+                offer(&sender, &dropped, Record::Update(update.clone()));
+            })))
+        } else {
+            None
+        };
+
+        let mut file = RotatingFile::new(config);
+        file.open()?;
+        let worker = thread::Builder::new()
+            .name("md-recorder".to_string())
+            .spawn(move || write_loop(file, receiver))?;
+
+        Ok(Recorder {
+            sender,
+            dropped,
+            tap,
+            observer,
+            worker,
+        })
+    }
+
+    // количество записей, отброшенных из-за переполнения канала
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /*
+    Останавливает запись: снимает отвод очереди и наблюдателя, дописывает
+    то, что уже в канале, и закрывает файл.
+    */
+    pub fn stop(self, queue: &TwoWayQueue, observable: &Observable) {
+        if let Some(handle) = self.tap {
+            queue.remove_tap(handle);
+        }
+        if let Some(handle) = &self.observer {
+            observable.remove_observer(handle);
+        }
+        let _ = self.sender.send(Message::Stop);
+        if self.worker.join().is_err() {
+            error!("recorder thread panicked");
+        }
+    }
+}
+
+fn offer(sender: &Sender<Message>, dropped: &AtomicU64, record: Record) {
+    match sender.try_send(Message::Record(Box::new(record))) {
+        Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+        Err(TrySendError::Full(_)) => {
+            if dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                warn!("recorder channel is full, records are being dropped");
+            }
+        }
+    }
+}
+
+fn write_loop(mut file: RotatingFile, receiver: Receiver<Message>) {
+    loop {
+        match receiver.recv_timeout(FLUSH_INTERVAL) {
+            Ok(Message::Stop) => break,
+            Ok(Message::Record(record)) => {
+                if let Err(e) = file.write(&record) {
+                    error!("recorder write failed: {:?}", e);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Err(e) = file.tick() {
+                    error!("recorder flush failed: {:?}", e);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    while let Ok(Message::Record(record)) = receiver.try_recv() {
+        if let Err(e) = file.write(&record) {
+            error!("recorder write failed: {:?}", e);
+        }
+    }
+    if let Err(e) = file.close() {
+        error!("recorder close failed: {:?}", e);
+    }
+}
+
+// открытый файл записи; finish дописывает буфер и, для zstd, завершающий фрейм
+enum Output {
+    Plain(BufWriter<File>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Output {
+    fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Output::Plain(file) => file,
+            Output::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(file) => file.write(buf),
+            Output::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(file) => file.flush(),
+            Output::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+struct RotatingFile {
+    config: RecorderConfig,
+    out: Option<Output>,
+    path: Option<PathBuf>,
+    written: u64,
+    opened_at: Instant,
+    sequence: u64, // номер следующего файла
+}
+
+impl RotatingFile {
+    fn new(config: RecorderConfig) -> Self {
+        RotatingFile {
+            config,
+            out: None,
+            path: None,
+            written: 0,
+            opened_at: Instant::now(),
+            sequence: 0,
+        }
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let mut name = format!(
+            "md-{}-{:04}.{}",
+            Local::now().format("%Y%m%d-%H%M%S%.3f"),
+            self.sequence,
+            self.config.format.extension()
+        );
+        if self.config.zstd {
+            name.push_str(".zst");
+        }
+        let path = self.config.dir.join(name);
+        let file = BufWriter::new(File::create_new(&path)?);
+        self.sequence += 1;
+
+        let mut out = if self.config.zstd {
+            Output::Zstd(zstd::Encoder::new(file, ZSTD_LEVEL)?)
+        } else {
+            Output::Plain(file)
+        };
+        self.written = self.config.format.write_header(&mut out)? as u64;
+        self.opened_at = Instant::now();
+        info!("recording market data to {}", path.display());
+        self.out = Some(out);
+        self.path = Some(path);
+        Ok(())
+    }
+
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        if self.needs_rotation() {
+            self.close()?;
+            self.open()?;
+        }
+        if let Some(out) = self.out.as_mut() {
+            self.written += self.config.format.write_record(out, record)? as u64;
+        }
+        Ok(())
+    }
+
+    // периодическая проверка в простое: сброс буфера и ротация по времени
+    fn tick(&mut self) -> io::Result<()> {
+        if self.out.is_some() && self.needs_rotation() {
+            self.close()?;
+            self.open()?;
+        } else if let Some(out) = self.out.as_mut() {
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    fn needs_rotation(&self) -> bool {
+        let by_size = self
+            .config
+            .rotate_bytes
+            .is_some_and(|limit| self.written >= limit);
+        let by_time = self
+            .config
+            .rotate_every
+            .is_some_and(|every| self.opened_at.elapsed() >= every);
+        by_size || by_time
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        if let Some(out) = self.out.take() {
+            out.finish()?;
+            if let Some(path) = self.path.take() {
+                debug!("closed {} ({} bytes)", path.display(), self.written);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format::RecordReader;
+
+    fn config(name: &str) -> RecorderConfig {
+        let dir = std::env::temp_dir().join(format!("ws-recorder-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        RecorderConfig {
+            dir,
+            format: RecordFormat::Jsonl,
+            rotate_bytes: None,
+            rotate_every: None,
+            zstd: false,
+            raw: true,
+            updates: false,
+        }
+    }
+
+    fn raw(text: &str) -> Record {
+        Record::Raw {
+            ts: 1,
+            text: text.to_string(),
+        }
+    }
+
+    // все записи из файлов каталога, файлы по имени
+    fn recorded(dir: &PathBuf) -> Vec<(String, Vec<Record>)> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        let result = files
+            .iter()
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().to_string();
+                let records = RecordReader::open(path)
+                    .unwrap()
+                    .collect::<io::Result<Vec<_>>>()
+                    .unwrap();
+                (name, records)
+            })
+            .collect();
+        fs::remove_dir_all(dir).unwrap();
+        result
+    }
+
+    // ротация на каждой записи: файлы открываются в одну миллисекунду и не затирают друг друга
+    #[test]
+    fn rotated_zstd_files_get_distinct_names_and_are_finished() {
+        let config = RecorderConfig {
+            rotate_bytes: Some(1),
+            zstd: true,
+            ..config("rotate")
+        };
+        let dir = config.dir.clone();
+        fs::create_dir_all(&dir).unwrap();
+        let mut file = RotatingFile::new(config);
+        file.open().unwrap();
+        for text in ["a", "b", "c"] {
+            file.write(&raw(text)).unwrap();
+        }
+        file.close().unwrap();
+
+        let files = recorded(&dir);
+        assert_eq!(files.len(), 3);
+        for (index, (name, records)) in files.iter().enumerate() {
+            assert!(
+                name.ends_with(&format!("-{:04}.jsonl.zst", index)),
+                "{}",
+                name
+            );
+            assert_eq!(records.len(), 1);
+        }
+    }
+
+    #[test]
+    fn stop_removes_the_queue_tap() {
+        let config = config("stop");
+        let dir = config.dir.clone();
+        let observable = Observable::new();
+        let queue = TwoWayQueue::new();
+        let recorder = Recorder::start(config, &queue, &observable).unwrap();
+        let tap = recorder.tap.unwrap();
+        queue.push("before".to_string());
+        recorder.stop(&queue, &observable);
+        queue.push("after".to_string());
+
+        assert!(!queue.remove_tap(tap));
+        let files = recorded(&dir);
+        assert_eq!(files.len(), 1);
+        let texts: Vec<&str> = files[0]
+            .1
+            .iter()
+            .map(|record| match record {
+                Record::Raw { text, .. } => text.as_str(),
+                Record::Update(_) => "update",
+            })
+            .collect();
+        assert_eq!(texts, ["before"]);
+    }
+}
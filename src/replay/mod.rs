@@ -0,0 +1,188 @@
+/*
+Офлайн-воспроизведение записанных рыночных данных и бэктест.
+
+replay читает файл Recorder и подает PriceUpdate в Observable::notify_observers
+с исходной скоростью, ускоренно в N раз или так быстро, как успевают подписчики.
+backtest поднимает движок brain с ReportSink вместо UDS и возвращает отчет.
+
+Сырые кадры (Record::Raw) пропускаются: в файле уже есть разобранные
+из них PriceUpdate.
+
+Запись, оборванная вместе с процессом, кончается неполной последней
+записью или незавершенным zstd-фреймом. Такой хвост - конец данных:
+replay предупреждает о нем, отмечает в ReplayStats::truncated и
+отдает все, что прочиталось до него.
+*/
+pub mod report;
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::brain::market::Market;
+use crate::brain::observer::Observable;
+use crate::brain::{initialize_observers, BrainConfig};
+use crate::recorder::format::{Record, RecordReader};
+use report::{ReplayReport, ReportSink};
+
+const BACKPRESSURE_PAUSE: Duration = Duration::from_micros(50);
+const DRAIN_IDLE_TIMEOUT: Duration = Duration::from_secs(10); // движок не забирает обновления
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplaySpeed {
+    Original,
+    Multiplier(f64),
+    Max,
+}
+
+impl ReplaySpeed {
+    // "original" | "max" | "10x" | "10"
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "original" | "1x" | "1" => Some(ReplaySpeed::Original),
+            "max" | "fast" => Some(ReplaySpeed::Max),
+            other => {
+                let factor: f64 = other.trim_end_matches('x').parse().ok()?;
+                (factor > 0.0).then_some(ReplaySpeed::Multiplier(factor))
+            }
+        }
+    }
+
+    fn factor(&self) -> Option<f64> {
+        match self {
+            ReplaySpeed::Original => Some(1.0),
+            ReplaySpeed::Multiplier(factor) => Some(*factor),
+            ReplaySpeed::Max => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ReplayStats {
+    pub updates: u64,
+    pub raw_skipped: u64,
+    pub first_ts: Option<i64>,
+    pub last_ts: Option<i64>,
+    pub wall_time: Duration,
+    pub truncated: bool, // последняя запись файла оборвана
+}
+
+#[derive(Clone, Debug)]
+pub struct BacktestConfig {
+    pub speed: ReplaySpeed,
+    pub fee_rate: f64, // комиссия за одну сделку, доля (0.001 = 0.1%)
+    pub notional: f64, // условный объем одной сделки в стартовой валюте
+}
+
+pub fn replay<P: AsRef<Path>>(
+    path: P,
+    observable: &Observable,
+    speed: ReplaySpeed,
+) -> io::Result<ReplayStats> {
+    let reader = RecordReader::open(&path)?;
+    info!(
+        "replaying {} ({:?}) at {:?}",
+        path.as_ref().display(),
+        reader.format(),
+        speed
+    );
+
+    let mut stats = ReplayStats::default();
+    let started = Instant::now();
+    // подписчики читают broadcast в своем темпе, не даем им отстать и потерять события
+    let high_water = observable.capacity() / 2;
+
+    for (index, record) in reader.enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                warn!(
+                    "{}: record {} is cut off ({}), replaying what was read before it",
+                    path.as_ref().display(),
+                    index,
+                    err
+                );
+                stats.truncated = true;
+                break;
+            }
+            Err(err) => return Err(err),
+        };
+        let update = match record {
+            Record::Update(update) => update,
+            Record::Raw { .. } => {
+                stats.raw_skipped += 1;
+                continue;
+            }
+        };
+
+        let first_ts = *stats.first_ts.get_or_insert(update.recv_ts);
+        if let Some(factor) = speed.factor() {
+            let offset_us = (update.recv_ts - first_ts).max(0) as f64 / factor;
+            let due = started + Duration::from_micros(offset_us as u64);
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+        while observable.pending() >= high_water {
+            thread::sleep(BACKPRESSURE_PAUSE);
+        }
+
+        stats.last_ts = Some(update.recv_ts);
+        stats.updates += 1;
+        observable.notify_observers(update);
+    }
+
+    stats.wall_time = started.elapsed();
+    Ok(stats)
+}
+
+/*
+Прогоняет запись через движок. Движок хранит состояние в глобальных
+структурах, поэтому бэктест - один на процесс.
+*/
+pub fn backtest<P: AsRef<Path>>(
+    path: P,
+    market: &Market,
+    brain: &BrainConfig,
+    config: &BacktestConfig,
+) -> io::Result<ReplayReport> {
+    let observable = Observable::new();
+    let sink = Arc::new(ReportSink::new(config.fee_rate, config.notional));
+
+    let engine = initialize_observers(&observable, market, brain, sink.clone());
+    let stats = replay(path, &observable, config.speed)?;
+
+    // движок забрал все обновления; remove_observer ждет, пока он досчитает последнее
+    let drained = wait_drained(&observable);
+    observable.remove_observer(&engine);
+    drained?;
+
+    Ok(sink.report(stats))
+}
+
+// ждет, пока подписчики разберут канал; ошибка, если очередь не двигается DRAIN_IDLE_TIMEOUT
+fn wait_drained(observable: &Observable) -> io::Result<()> {
+    let mut pending = observable.pending();
+    let mut progressed = Instant::now();
+    while pending > 0 {
+        thread::sleep(Duration::from_millis(1));
+        let now_pending = observable.pending();
+        if now_pending < pending {
+            progressed = Instant::now();
+        } else if progressed.elapsed() > DRAIN_IDLE_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "engine took no update for {:?}, {} left unprocessed",
+                    DRAIN_IDLE_TIMEOUT, now_pending
+                ),
+            ));
+        }
+        pending = now_pending;
+    }
+    Ok(())
+}
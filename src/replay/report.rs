@@ -0,0 +1,365 @@
+/*
+Отчет бэктеста.
+
+Возможности и их длительность берутся из трекера движка: сигнал приходит
+при открытии и существенных изменениях, закрытие - через SignalSink::closed.
+Возможность учитывается в отчете по первому дошедшему до sink сигналу,
+PnL считается по нему же: notional * (earn% / 100 - 3 * fee_rate).
+*/
+use bigdecimal::ToPrimitive;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use super::ReplayStats;
+use crate::brain::signal::{Signal, SignalSink};
+use crate::brain::tracker::Opportunity;
+use crate::brain::triangle::TriangleKey;
+
+const LEGS: f64 = 3.0;
+
+#[derive(Default)]
+struct TriangleStats {
+    signals: u64,
+    earns: Vec<f64>,     // earn на первом сигнале каждой возможности, %
+    durations: Vec<f64>, // длительность каждой закрытой возможности, мс
+    pnl: f64,
+}
+
+#[derive(Default)]
+struct ReportState {
+    triangles: HashMap<TriangleKey, TriangleStats>,
+    open: HashMap<TriangleKey, (i64, i64)>, // started_ts, ts последнего сигнала
+}
+
+pub struct ReportSink {
+    fee_rate: f64,
+    notional: f64,
+    state: Mutex<ReportState>,
+}
+
+impl ReportSink {
+    pub fn new(fee_rate: f64, notional: f64) -> Self {
+        ReportSink {
+            fee_rate,
+            notional,
+            state: Mutex::new(ReportState::default()),
+        }
+    }
+
+    pub fn report(&self, stats: ReplayStats) -> ReplayReport {
+        let mut state = self.state.lock().unwrap();
+
+        // незакрытые к концу записи считаем до последнего сигнала
+        let open: Vec<_> = state.open.drain().collect();
+        for (key, (started_ts, last_ts)) in open {
+            let stats = state.triangles.entry(key).or_default();
+            stats.durations.push((last_ts - started_ts) as f64 / 1000.0);
+        }
+
+        let mut all_earns = Vec::new();
+        let mut triangles: Vec<TriangleReport> = state
+            .triangles
+            .iter()
+            .map(|(key, t)| {
+                all_earns.extend_from_slice(&t.earns);
+                TriangleReport {
+                    key: key.clone(),
+                    signals: t.signals,
+                    opportunities: t.earns.len() as u64,
+                    earn: Distribution::from_values(&t.earns),
+                    persistence_ms: Distribution::from_values(&t.durations),
+                    pnl: t.pnl,
+                }
+            })
+            .collect();
+        triangles.sort_by(|a, b| {
+            b.opportunities
+                .cmp(&a.opportunities)
+                .then_with(|| b.pnl.total_cmp(&a.pnl))
+        });
+
+        ReplayReport {
+            stats,
+            fee_rate: self.fee_rate,
+            notional: self.notional,
+            signals: triangles.iter().map(|t| t.signals).sum(),
+            opportunities: triangles.iter().map(|t| t.opportunities).sum(),
+            pnl: triangles.iter().map(|t| t.pnl).sum(),
+            earn: Distribution::from_values(&all_earns),
+            triangles,
+        }
+    }
+}
+
+impl SignalSink for ReportSink {
+    fn emit(&self, batch: &[Signal]) {
+        let mut state = self.state.lock().unwrap();
+        for signal in batch {
+            self.account(&mut state, signal);
+        }
+    }
+
+    fn closed(&self, opportunity: &Opportunity) {
+        let mut state = self.state.lock().unwrap();
+        state.open.remove(&opportunity.key);
+        let stats = state.triangles.entry(opportunity.key.clone()).or_default();
+        stats
+            .durations
+            .push(opportunity.duration().as_secs_f64() * 1000.0);
+    }
+}
+
+impl ReportSink {
+    fn account(&self, state: &mut ReportState, signal: &Signal) {
+        let earn = signal.earn.to_f64().unwrap_or(0.0);
+
+        // первый сигнал возможности может быть и Update, если Open придержал cooldown
+        let first = state
+            .open
+            .insert(signal.triangle_key.clone(), (signal.started_ts, signal.ts))
+            .is_none_or(|(started_ts, _)| started_ts != signal.started_ts);
+
+        let stats = state
+            .triangles
+            .entry(signal.triangle_key.clone())
+            .or_default();
+        stats.signals += 1;
+        if first {
+            stats.earns.push(earn);
+            stats.pnl += self.notional * (earn / 100.0 - LEGS * self.fee_rate);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Distribution {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+impl Distribution {
+    fn from_values(values: &[f64]) -> Self {
+        if values.is_empty() {
+            return Distribution::default();
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+
+        Distribution {
+            count: sorted.len(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "min {:.4} p50 {:.4} p90 {:.4} p99 {:.4} max {:.4} mean {:.4}",
+            self.min, self.p50, self.p90, self.p99, self.max, self.mean
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TriangleReport {
+    pub key: TriangleKey,
+    pub signals: u64,
+    pub opportunities: u64,
+    pub earn: Distribution,           // %
+    pub persistence_ms: Distribution, // мс
+    pub pnl: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplayReport {
+    pub stats: ReplayStats,
+    pub fee_rate: f64,
+    pub notional: f64,
+    pub signals: u64,
+    pub opportunities: u64,
+    pub pnl: f64,
+    pub earn: Distribution,
+    pub triangles: Vec<TriangleReport>,
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span_s = match (self.stats.first_ts, self.stats.last_ts) {
+            (Some(first), Some(last)) => (last - first) as f64 / 1_000_000.0,
+            _ => 0.0,
+        };
+        writeln!(
+            f,
+            "replayed {} updates ({} raw frames skipped), {:.1}s of data in {:.1}s",
+            self.stats.updates,
+            self.stats.raw_skipped,
+            span_s,
+            self.stats.wall_time.as_secs_f64()
+        )?;
+        if self.stats.truncated {
+            writeln!(f, "the last record of the file is cut off")?;
+        }
+        writeln!(
+            f,
+            "signals {}, opportunities {}, simulated PnL {:.6} (notional {}, fee {} per leg)",
+            self.signals, self.opportunities, self.pnl, self.notional, self.fee_rate
+        )?;
+        writeln!(f, "earn %: {}", self.earn)?;
+
+        for t in &self.triangles {
+            writeln!(f)?;
+            writeln!(f, "{}", t.key)?;
+            writeln!(
+                f,
+                "  opportunities {}, signals {}, PnL {:.6}",
+                t.opportunities, t.signals, t.pnl
+            )?;
+            writeln!(f, "  earn %:         {}", t.earn)?;
+            writeln!(f, "  persistence ms: {}", t.persistence_ms)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::tracker::OpportunityEvent;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+
+    fn key(start: &str) -> TriangleKey {
+        TriangleKey {
+            a: "BTCUSDT".to_string(),
+            b: "ETHBTC".to_string(),
+            c: "ETHUSDT".to_string(),
+            d: "BUY".to_string(),
+            start: start.to_string(),
+        }
+    }
+
+    fn signal(key: &TriangleKey, earn: &str, started_ts: i64, ts: i64) -> Signal {
+        let earn = BigDecimal::from_str(earn).unwrap();
+        Signal {
+            triangle_key: key.clone(),
+            final_amount: BigDecimal::from(1),
+            gross_bps: &earn * BigDecimal::from(100),
+            net_bps: &earn * BigDecimal::from(100),
+            earn,
+            event: if started_ts == ts {
+                OpportunityEvent::Open
+            } else {
+                OpportunityEvent::Update
+            },
+            started_ts,
+            order_size: None,
+            profit: None,
+            ts,
+            trace: Default::default(),
+        }
+    }
+
+    fn closed(key: &TriangleKey, started_ts: i64, last_ts: i64) -> Opportunity {
+        Opportunity {
+            key: key.clone(),
+            started_ts,
+            last_ts,
+            final_amount: BigDecimal::from(1),
+            earn: BigDecimal::from(0),
+            net_earn: BigDecimal::from(0),
+            peak_earn: BigDecimal::from(0),
+            updates: 0,
+        }
+    }
+
+    #[test]
+    fn opportunity_is_counted_once_by_its_first_signal() {
+        let sink = ReportSink::new(0.001, 100.0);
+        let usdt = key("USDT");
+        // открытие и обновление одной возможности, закрытие через 2 мс, потом вторая
+        sink.emit(&[signal(&usdt, "0.5", 1_000, 1_000)]);
+        sink.emit(&[signal(&usdt, "0.9", 1_000, 2_000)]);
+        sink.closed(&closed(&usdt, 1_000, 3_000));
+        sink.emit(&[signal(&usdt, "0.4", 10_000, 10_000)]);
+        sink.closed(&closed(&usdt, 10_000, 14_000));
+
+        let report = sink.report(ReplayStats::default());
+        assert_eq!((report.signals, report.opportunities), (3, 2));
+        let triangle = &report.triangles[0];
+        assert_eq!(triangle.earn.count, 2);
+        assert_eq!((triangle.earn.min, triangle.earn.max), (0.4, 0.5));
+        assert_eq!(triangle.persistence_ms.count, 2);
+        assert_eq!(
+            (triangle.persistence_ms.min, triangle.persistence_ms.max),
+            (2.0, 4.0)
+        );
+        // 100 * (0.005 - 0.003) + 100 * (0.004 - 0.003)
+        assert!((report.pnl - 0.3).abs() < 1e-9, "{}", report.pnl);
+    }
+
+    #[test]
+    fn open_at_the_end_lasts_until_its_last_signal() {
+        let sink = ReportSink::new(0.0, 100.0);
+        let usdt = key("USDT");
+        let btc = key("BTC");
+        sink.emit(&[signal(&usdt, "0.1", 1_000, 1_000)]);
+        sink.emit(&[
+            signal(&btc, "0.2", 2_000, 2_000),
+            signal(&usdt, "0.2", 1_000, 6_000),
+        ]);
+        sink.emit(&[signal(&btc, "0.3", 2_000, 3_000)]);
+        sink.closed(&closed(&btc, 2_000, 4_000));
+        sink.emit(&[signal(&btc, "0.6", 8_000, 8_000)]);
+
+        let report = sink.report(ReplayStats::default());
+        assert_eq!((report.signals, report.opportunities), (5, 3));
+        assert_eq!(report.earn.count, 3);
+        assert_eq!(report.earn.max, 0.6);
+
+        // больше возможностей - выше в отчете
+        let keys: Vec<&str> = report
+            .triangles
+            .iter()
+            .map(|t| t.key.start.as_str())
+            .collect();
+        assert_eq!(keys, ["BTC", "USDT"]);
+        let usdt = &report.triangles[1];
+        assert_eq!(
+            (usdt.persistence_ms.count, usdt.persistence_ms.max),
+            (1, 5.0)
+        );
+        let btc = &report.triangles[0];
+        // закрытая за 2 мс и открытая последним сигналом: 0 мс
+        assert_eq!((btc.persistence_ms.min, btc.persistence_ms.max), (0.0, 2.0));
+    }
+
+    #[test]
+    fn distribution_percentiles_round_to_nearest_rank() {
+        let values: Vec<f64> = (1..=11).map(f64::from).collect();
+        let distribution = Distribution::from_values(&values);
+        assert_eq!(distribution.count, 11);
+        assert_eq!(
+            (distribution.min, distribution.max, distribution.mean),
+            (1.0, 11.0, 6.0)
+        );
+        assert_eq!(
+            (distribution.p50, distribution.p90, distribution.p99),
+            (6.0, 10.0, 11.0)
+        );
+        assert_eq!(Distribution::from_values(&[]).count, 0);
+    }
+}
@@ -20,7 +20,7 @@ pub type SymbolData = Arc<
 pub type SymbolDataMap = Arc<DashMap<String, SymbolData>>; //symbol - SymbolData (price-volume-refs)
 
 pub fn create_symbol_data_map(
-    pairs: &Vec<String>,
+    pairs: &[String],
     triangles: &HashMap<TriangleKey, Vec<TriangleElement>>,
 ) -> SymbolDataMap {
     let symbol_data_map: SymbolDataMap = Arc::new(DashMap::new());
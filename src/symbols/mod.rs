@@ -0,0 +1,261 @@
+/*
+Торговые правила символов биржи.
+
+Загружаются из локального файла в формате ответа exchangeInfo:
+{"symbols": [{"symbol": "BTCUSDT", "status": "TRADING", "baseAsset": "BTC",
+  "quoteAsset": "USDT", "filters": [{"filterType": "PRICE_FILTER", "tickSize": "0.01"},
+  {"filterType": "LOT_SIZE", "stepSize": "0.00001", "minQty": "0.00001"},
+  {"filterType": "NOTIONAL", "minNotional": "5"}]}]}
+Неизвестные поля и фильтры пропускаются. Отсутствующий фильтр - нулевой шаг
+или минимум, т.е. ограничения нет.
+*/
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::brain::fixed::{Fixed, Price, Qty};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub tick_size: Price,    // шаг цены
+    pub step_size: Qty,      // шаг количества базовой валюты
+    pub min_qty: Qty,        // минимальное количество базовой валюты
+    pub min_notional: Fixed, // минимальная сумма сделки в котируемой валюте
+}
+
+impl SymbolInfo {
+    // количество базовой валюты вниз до шага лота
+    pub fn round_qty(&self, qty: Qty) -> Qty {
+        qty.round_to_step(self.step_size)
+    }
+
+    // цена вниз до шага цены
+    pub fn round_price(&self, price: Price) -> Price {
+        price.round_to_tick(self.tick_size)
+    }
+
+    // проходит ли сделка qty по price минимальные ограничения биржи
+    pub fn accepts(&self, qty: Qty, price: Price) -> bool {
+        if qty.is_zero() || qty < self.min_qty {
+            return false;
+        }
+        match qty.notional(price) {
+            Ok(notional) => notional >= self.min_notional,
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SymbolRegistry {
+    symbols: HashMap<String, SymbolInfo>,
+    by_assets: HashMap<(String, String), String>, // (base, quote) - symbol
+}
+
+impl SymbolRegistry {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::from_json(&text)
+    }
+
+    pub fn from_json(text: &str) -> io::Result<Self> {
+        let info: ExchangeInfo = serde_json::from_str(text)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let mut registry = SymbolRegistry::default();
+        for raw in info.symbols {
+            // снятые с торгов символы в треугольники не попадают
+            if raw
+                .status
+                .as_deref()
+                .is_some_and(|status| status != "TRADING")
+            {
+                continue;
+            }
+            registry.insert(raw.into_info()?);
+        }
+        Ok(registry)
+    }
+
+    pub fn insert(&mut self, info: SymbolInfo) {
+        self.by_assets.insert(
+            (info.base_asset.clone(), info.quote_asset.clone()),
+            info.symbol.clone(),
+        );
+        self.symbols.insert(info.symbol.clone(), info);
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&SymbolInfo> {
+        self.symbols.get(symbol)
+    }
+
+    // символ, где base - базовая валюта, quote - котируемая
+    pub fn find(&self, base: &str, quote: &str) -> Option<&SymbolInfo> {
+        let symbol = self.by_assets.get(&(base.to_string(), quote.to_string()))?;
+        self.symbols.get(symbol)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SymbolInfo> {
+        self.symbols.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<RawSymbol>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSymbol {
+    symbol: String,
+    status: Option<String>,
+    base_asset: String,
+    quote_asset: String,
+    #[serde(default)]
+    filters: Vec<RawFilter>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawFilter {
+    filter_type: String,
+    tick_size: Option<String>,
+    step_size: Option<String>,
+    min_qty: Option<String>,
+    min_notional: Option<String>,
+}
+
+impl RawSymbol {
+    fn into_info(self) -> io::Result<SymbolInfo> {
+        let mut info = SymbolInfo {
+            symbol: self.symbol,
+            base_asset: self.base_asset,
+            quote_asset: self.quote_asset,
+            tick_size: Price::ZERO,
+            step_size: Qty::ZERO,
+            min_qty: Qty::ZERO,
+            min_notional: Fixed::ZERO,
+        };
+        for filter in self.filters {
+            let symbol = &info.symbol;
+            let value = |field: Option<String>| -> io::Result<Fixed> {
+                match field {
+                    Some(text) => Fixed::parse(&text).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{}: bad {} value {:?}", symbol, filter.filter_type, text),
+                        )
+                    }),
+                    None => Ok(Fixed::ZERO),
+                }
+            };
+            match filter.filter_type.as_str() {
+                "PRICE_FILTER" => info.tick_size = Price::new(value(filter.tick_size.clone())?),
+                "LOT_SIZE" => {
+                    info.step_size = Qty::new(value(filter.step_size.clone())?);
+                    info.min_qty = Qty::new(value(filter.min_qty.clone())?);
+                }
+                "MIN_NOTIONAL" | "NOTIONAL" => {
+                    info.min_notional = value(filter.min_notional.clone())?
+                }
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXCHANGE_INFO: &str = r#"{"symbols": [
+        {"symbol": "BTCUSDT", "status": "TRADING", "baseAsset": "BTC", "quoteAsset": "USDT",
+         "filters": [{"filterType": "PRICE_FILTER", "tickSize": "0.01"},
+                     {"filterType": "LOT_SIZE", "stepSize": "0.00001", "minQty": "0.0001"},
+                     {"filterType": "NOTIONAL", "minNotional": "5"},
+                     {"filterType": "ICEBERG_PARTS", "limit": 10}]},
+        {"symbol": "ETHBTC", "status": "BREAK", "baseAsset": "ETH", "quoteAsset": "BTC"},
+        {"symbol": "ETHUSDT", "baseAsset": "ETH", "quoteAsset": "USDT"}
+    ]}"#;
+
+    fn fixed(value: &str) -> Fixed {
+        Fixed::parse(value).unwrap()
+    }
+
+    #[test]
+    fn loads_trading_symbols_with_their_filters() {
+        let registry = SymbolRegistry::from_json(EXCHANGE_INFO).unwrap();
+        assert_eq!(registry.len(), 2);
+        assert!(registry.get("ETHBTC").is_none());
+
+        let btc = registry.find("BTC", "USDT").unwrap();
+        assert_eq!(btc.symbol, "BTCUSDT");
+        assert_eq!(btc.tick_size, Price::new(fixed("0.01")));
+        assert_eq!(btc.step_size, Qty::new(fixed("0.00001")));
+        assert_eq!(btc.min_qty, Qty::new(fixed("0.0001")));
+        assert_eq!(btc.min_notional, fixed("5"));
+        assert!(registry.find("USDT", "BTC").is_none());
+
+        // без фильтров ограничений нет
+        let eth = registry.get("ETHUSDT").unwrap();
+        assert_eq!((eth.step_size, eth.min_notional), (Qty::ZERO, Fixed::ZERO));
+        assert!(eth.accepts(Qty::new(fixed("0.0000000001")), Price::new(fixed("1"))));
+    }
+
+    #[test]
+    fn bad_filter_value_is_an_error() {
+        let text = r#"{"symbols": [{"symbol": "BTCUSDT", "baseAsset": "BTC", "quoteAsset": "USDT",
+            "filters": [{"filterType": "LOT_SIZE", "stepSize": "1e-5"}]}]}"#;
+        let err = SymbolRegistry::from_json(text).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(
+            err.to_string().contains("BTCUSDT: bad LOT_SIZE value"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn rounds_down_to_step_and_tick() {
+        let registry = SymbolRegistry::from_json(EXCHANGE_INFO).unwrap();
+        let btc = registry.get("BTCUSDT").unwrap();
+        assert_eq!(
+            btc.round_qty(Qty::new(fixed("0.123456789"))),
+            Qty::new(fixed("0.12345"))
+        );
+        assert_eq!(
+            btc.round_price(Price::new(fixed("60000.129"))),
+            Price::new(fixed("60000.12"))
+        );
+        // уже кратное шагу не меняется
+        assert_eq!(btc.round_qty(Qty::new(fixed("1"))), Qty::new(fixed("1")));
+    }
+
+    #[test]
+    fn accepts_orders_from_min_qty_and_min_notional() {
+        let registry = SymbolRegistry::from_json(EXCHANGE_INFO).unwrap();
+        let btc = registry.get("BTCUSDT").unwrap();
+        let price = Price::new(fixed("50000"));
+
+        // 0.0001 * 50000 = 5: ровно на минимуме
+        assert!(btc.accepts(Qty::new(fixed("0.0001")), price));
+        assert!(!btc.accepts(Qty::new(fixed("0.00009")), Price::new(fixed("100000"))));
+        // количество выше min_qty, но сумма 4.99995 меньше 5
+        assert!(!btc.accepts(Qty::new(fixed("0.0001")), Price::new(fixed("49999.5"))));
+        assert!(!btc.accepts(Qty::ZERO, price));
+    }
+}
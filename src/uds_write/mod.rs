@@ -1,16 +1,219 @@
+/*
+Запись сигналов в UDS.
+
+Сигналы не пишутся в сокет напрямую из потока наблюдателя: они кладутся
+в канал, а отдельная асинхронная задача (UdsWriter) держит соединение,
+переподключается при обрыве и пишет сообщения по порядку.
+Отправка в канал никогда не блокирует и не требует tokio runtime у вызывающего.
+
+Сообщение с меткой (send_traced) после записи в сокет закрывает стадию emit
+и попадает в гистограммы latency.
+
+Доставка - не реже одного раза. Сообщение, запись которого оборвалась,
+после переподключения повторяется в новом соединении целиком. Сообщения
+разделяются \n (send добавляет его, если нет), так что читатель
+отбрасывает неполную последнюю строку оборванного соединения.
+
+Задача завершается, когда закрыты все отправители: в соединении - дописав
+канал, без соединения - не дожидаясь его и отбросив недописанное.
+UdsWriter::finish закрывает свою копию отправителя и ждет этого.
+*/
 use std::io;
+use std::time::Duration;
 use tokio::io::AsyncWriteExt;
 use tokio::net::UnixStream;
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, error, warn};
+
+use crate::latency::{self, Trace};
+
+pub const DEFAULT_SOCKET_PATH: &str = "/tmp/arm_arbitr_socket";
+
+const CHANNEL_CAPACITY: usize = 1024;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
 
 pub async fn uds_connect(socket_path: &str) -> io::Result<UnixStream> {
     UnixStream::connect(socket_path).await
 }
 
-pub async fn uds_write_to(stream: &mut UnixStream, msg: &str) {
-    // Отправляем сообщение
-    if let Err(e) = stream.write_all(msg.as_bytes()).await {
-        eprintln!("Ошибка при отправке: {:?}", e);
-    } else {
-        // println!("Отправлено сообщение: {}", msg);
+pub async fn uds_write_to(stream: &mut UnixStream, msg: &str) -> io::Result<()> {
+    stream.write_all(msg.as_bytes()).await
+}
+
+#[derive(Clone, Debug)]
+pub struct UdsWriter {
+    sender: mpsc::Sender<(String, Option<Trace>)>,
+    done: watch::Receiver<()>, // отправитель живет в задаче записи, закрывается с ней
+}
+
+impl UdsWriter {
+    /*
+    Запускает задачу записи. Если вызвано внутри tokio runtime - задача
+    запускается в нем, иначе поднимается отдельный поток со своим runtime.
+    */
+    pub fn spawn(socket_path: &str) -> io::Result<Self> {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let (finished, done) = watch::channel(());
+        let socket_path = socket_path.to_string();
+        let task = async move {
+            write_loop(socket_path, receiver).await;
+            drop(finished);
+        };
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(task);
+            }
+            Err(_) => {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?;
+                std::thread::Builder::new()
+                    .name("uds-writer".to_string())
+                    .spawn(move || runtime.block_on(task))?;
+            }
+        }
+
+        Ok(UdsWriter { sender, done })
+    }
+
+    /*
+    Закрывает эту копию и ждет конца задачи записи. Задача кончится, когда
+    закроются и остальные копии (например, вместе с наблюдателем движка).
+    */
+    pub async fn finish(self) {
+        let UdsWriter { sender, mut done } = self;
+        drop(sender);
+        while done.changed().await.is_ok() {}
+    }
+
+    // Неблокирующая отправка. При переполнении канала сообщение отбрасывается
+    pub fn send(&self, msg: String) {
+        self.try_send(msg, None);
+    }
+
+    // то же с метками стадий сигнала
+    pub fn send_traced(&self, msg: String, trace: Trace) {
+        self.try_send(msg, Some(trace));
+    }
+
+    fn try_send(&self, mut msg: String, trace: Option<Trace>) {
+        if !msg.ends_with('\n') {
+            msg.push('\n');
+        }
+        match self.sender.try_send((msg, trace)) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full((msg, _))) => {
+                warn!(
+                    "uds writer queue is full, signal dropped: {}",
+                    msg.trim_end()
+                );
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                error!("uds writer task is gone, signal dropped");
+            }
+        }
+    }
+}
+
+async fn write_loop(socket_path: String, mut receiver: mpsc::Receiver<(String, Option<Trace>)>) {
+    // сообщение, которое не удалось записать до обрыва, отправим после переподключения
+    let mut pending: Option<(String, Option<Trace>)> = None;
+
+    loop {
+        let mut stream = match uds_connect(&socket_path).await {
+            Ok(stream) => {
+                debug!("uds connected to {}", socket_path);
+                stream
+            }
+            Err(e) => {
+                error!("uds connect to {} failed: {:?}", socket_path, e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                // отправителей больше нет: ждать соединения незачем
+                if receiver.is_closed() {
+                    let lost = receiver.len() + pending.iter().count();
+                    if lost > 0 {
+                        warn!(
+                            "uds writer stopped without connection, {} signals dropped",
+                            lost
+                        );
+                    }
+                    return;
+                }
+                continue;
+            }
+        };
+
+        loop {
+            let msg = match pending.take() {
+                Some(msg) => msg,
+                None => match receiver.recv().await {
+                    Some(msg) => msg,
+                    None => return, // все отправители закрыты
+                },
+            };
+
+            let (msg, trace) = msg;
+            if let Err(e) = uds_write_to(&mut stream, &msg).await {
+                error!("uds write failed: {:?}", e);
+                pending = Some((msg, trace));
+                break;
+            }
+            if let Some(mut trace) = trace {
+                trace.written = latency::now_us();
+                latency::record(&trace);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::UnixListener;
+
+    fn socket_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ws-uds-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    #[tokio::test]
+    async fn messages_are_newline_delimited() {
+        let path = socket_path("lines");
+        let listener = UnixListener::bind(&path).unwrap();
+        let writer = UdsWriter::spawn(&path).unwrap();
+        writer.send("first".to_string());
+        writer.send("second\n".to_string());
+        // соединение принимается очередью сокета, finish дожидается записи
+        tokio::time::timeout(Duration::from_secs(5), writer.finish())
+            .await
+            .unwrap();
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut received = String::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut received))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, "first\nsecond\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_without_connection_when_senders_are_gone() {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        sender.try_send(("lost\n".to_string(), None)).unwrap();
+        drop(sender);
+        let finished = tokio::time::timeout(
+            RECONNECT_DELAY * 5,
+            write_loop(socket_path("gone"), receiver),
+        )
+        .await;
+        assert!(
+            finished.is_ok(),
+            "writer keeps reconnecting after senders closed"
+        );
     }
 }
@@ -0,0 +1,65 @@
+/*
+Кадры потока bookTicker.
+
+Одиночный поток: {"u":400900217,"s":"BNBUSDT","b":"25.35","B":"31.21","a":"25.36","A":"40.66"}
+Комбинированный: {"stream":"bnbusdt@bookTicker","data":{...}}
+Время события "E" есть не у всех бирж, без него exchange_ts - None.
+Ответы на SUBSCRIBE ({"result":null,"id":1}) и прочие кадры разбор пропускает.
+*/
+use serde::Deserialize;
+
+use crate::brain::observer::PriceUpdate;
+use crate::latency::Trace;
+
+#[derive(Deserialize)]
+struct Frame {
+    data: Option<Ticker>,
+    #[serde(flatten)]
+    ticker: Option<Ticker>,
+}
+
+#[derive(Deserialize)]
+struct Ticker {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "b")]
+    bid: String,
+    #[serde(rename = "B")]
+    bid_qty: String,
+    #[serde(rename = "a")]
+    ask: String,
+    #[serde(rename = "A")]
+    ask_qty: String,
+    #[serde(rename = "E")]
+    event_time: Option<i64>,
+}
+
+// None - кадр не bookTicker
+pub fn parse(text: &str, recv_ts: i64) -> Option<PriceUpdate> {
+    let frame: Frame = serde_json::from_str(text).ok()?;
+    let ticker = frame.data.or(frame.ticker)?;
+    Some(PriceUpdate {
+        symbol: ticker.symbol,
+        bid: ticker.bid,
+        ask: ticker.ask,
+        bid_qty: ticker.bid_qty,
+        ask_qty: ticker.ask_qty,
+        exchange_ts: ticker.event_time,
+        recv_ts,
+        trace: Trace::default(),
+    })
+}
+
+// подписка на bookTicker символов одним сообщением
+pub fn subscribe_message(symbols: &[String], id: u64) -> String {
+    let params: Vec<String> = symbols
+        .iter()
+        .map(|symbol| format!("{}@bookTicker", symbol.to_ascii_lowercase()))
+        .collect();
+    serde_json::json!({
+        "method": "SUBSCRIBE",
+        "params": params,
+        "id": id,
+    })
+    .to_string()
+}
@@ -1,3 +1,5 @@
+pub mod book_ticker;
+
 use async_trait::async_trait;
 // use chrono::{Local, Utc};
 // use chrono::Utc;
@@ -6,6 +8,7 @@ use std::sync::{Arc, Mutex};
 use tracing::debug;
 use url::Url;
 
+use crate::error;
 use crate::queue::OUTCOMING_QUEUE;
 
 pub enum Call {
@@ -58,8 +61,11 @@ impl ezsockets::ClientExt for WebSocketClient {
 }
 
 impl WebSocketClient {
-    pub async fn new(url: &str) -> Arc<Self> {
-        let url = Url::parse(url).unwrap();
+    pub async fn new(url: &str) -> error::Result<Arc<Self>> {
+        let url = Url::parse(url).map_err(|source| error::Error::Url {
+            url: url.to_string(),
+            source,
+        })?;
         let config = ClientConfig::new(url);
         let (handle, future) = ezsockets::connect(
             |handle| WebSocketClient {
@@ -74,7 +80,7 @@ impl WebSocketClient {
             initialized: true,
         });
         tokio::spawn(future);
-        client
+        Ok(client)
     }
 
     pub fn send_message(&self, message: &str) {
@@ -0,0 +1,303 @@
+use futures_util::{SinkExt, StreamExt};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use ws::brain::observer::Observable;
+use ws::mock_exchange::{
+    Feed, MockExchange, MockExchangeConfig, MockTick, RandomWalk, SlowConsumer,
+};
+use ws::queue::OUTCOMING_QUEUE;
+use ws::websocket_client::{book_ticker, WebSocketClient};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const WAIT: Duration = Duration::from_secs(5);
+
+async fn connect(exchange: &MockExchange) -> Client {
+    connect_async(exchange.url()).await.unwrap().0
+}
+
+// следующее сообщение; ping и pong пропускаются
+async fn next_message(client: &mut Client) -> Message {
+    loop {
+        let message = tokio::time::timeout(WAIT, client.next())
+            .await
+            .expect("no message from mock exchange")
+            .expect("stream ended")
+            .unwrap();
+        if !message.is_ping() && !message.is_pong() {
+            return message;
+        }
+    }
+}
+
+async fn request(client: &mut Client, text: &str) -> serde_json::Value {
+    client.send(Message::text(text)).await.unwrap();
+    let reply = next_message(client).await;
+    serde_json::from_str(reply.to_text().unwrap()).unwrap()
+}
+
+async fn subscribe(client: &mut Client, symbols: &[&str]) {
+    let symbols: Vec<String> = symbols.iter().map(|s| s.to_string()).collect();
+    let reply = request(client, &book_ticker::subscribe_message(&symbols, 1)).await;
+    assert_eq!(reply, serde_json::json!({"result": null, "id": 1}));
+}
+
+fn close_code(message: &Message) -> Option<u16> {
+    match message {
+        Message::Close(Some(frame)) => Some(frame.code.into()),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn answers_subscription_requests() {
+    let exchange = MockExchange::start(MockExchangeConfig::default())
+        .await
+        .unwrap();
+    let mut client = connect(&exchange).await;
+
+    subscribe(&mut client, &["BTCUSDT", "ETHUSDT"]).await;
+    let list = request(&mut client, r#"{"method":"LIST_SUBSCRIPTIONS","id":2}"#).await;
+    assert_eq!(
+        list,
+        serde_json::json!({"result": ["btcusdt@bookTicker", "ethusdt@bookTicker"], "id": 2})
+    );
+
+    let reply = request(
+        &mut client,
+        r#"{"method":"UNSUBSCRIBE","params":["ethusdt@bookTicker"],"id":3}"#,
+    )
+    .await;
+    assert_eq!(reply["id"], 3);
+    let list = request(&mut client, r#"{"method":"LIST_SUBSCRIPTIONS","id":4}"#).await;
+    assert_eq!(list["result"], serde_json::json!(["btcusdt@bookTicker"]));
+
+    let reply = request(&mut client, r#"{"method":"TRADE","id":5}"#).await;
+    assert_eq!(reply["error"]["code"], 2);
+    let reply = request(&mut client, "not json").await;
+    assert!(reply["error"].is_object());
+
+    let stats = exchange.stats();
+    assert_eq!(stats.connections, 1);
+    assert_eq!(stats.subscriptions, 2);
+    assert_eq!(stats.requests, 6);
+}
+
+#[tokio::test]
+async fn streams_scripted_ticks_for_subscribed_symbols() {
+    let ticks = vec![
+        MockTick::new("BTCUSDT", "60000.1", "60000.2"),
+        MockTick::new("ETHUSDT", "3000.5", "3000.6"),
+        MockTick::new("BTCUSDT", "60001.1", "60001.2"),
+    ];
+    let exchange = MockExchange::start(MockExchangeConfig {
+        feed: Feed::Scripted {
+            ticks,
+            repeat: false,
+        },
+        tick_interval: Duration::from_millis(1),
+        ..MockExchangeConfig::default()
+    })
+    .await
+    .unwrap();
+    let mut client = connect(&exchange).await;
+    subscribe(&mut client, &["BTCUSDT"]).await;
+
+    for bid in ["60000.1", "60001.1"] {
+        let frame = next_message(&mut client).await;
+        let update = book_ticker::parse(frame.to_text().unwrap(), 0).unwrap();
+        assert_eq!(update.symbol, "BTCUSDT");
+        assert_eq!(update.bid, bid);
+        assert!(update.exchange_ts.is_some());
+    }
+}
+
+#[tokio::test]
+async fn random_walk_streams_every_subscribed_pair() {
+    let exchange = MockExchange::start(MockExchangeConfig {
+        feed: Feed::RandomWalk(RandomWalk::new(&[
+            ("BTCUSDT", 60000.0),
+            ("ETHBTC", 0.05),
+            ("ETHUSDT", 3000.0),
+        ])),
+        tick_interval: Duration::from_millis(1),
+        ..MockExchangeConfig::default()
+    })
+    .await
+    .unwrap();
+    let mut client = connect(&exchange).await;
+    subscribe(&mut client, &["BTCUSDT", "ETHBTC"]).await;
+
+    let mut seen = std::collections::BTreeSet::new();
+    for _ in 0..20 {
+        let frame = next_message(&mut client).await;
+        let update = book_ticker::parse(frame.to_text().unwrap(), 0).unwrap();
+        let bid: f64 = update.bid.parse().unwrap();
+        let ask: f64 = update.ask.parse().unwrap();
+        assert!(bid < ask, "{:?}", update);
+        seen.insert(update.symbol);
+    }
+    assert_eq!(
+        seen.into_iter().collect::<Vec<_>>(),
+        vec!["BTCUSDT", "ETHBTC"]
+    );
+}
+
+#[test]
+fn random_walk_is_reproducible_by_seed() {
+    let walk = RandomWalk::new(&[("BTCUSDT", 60000.0), ("ETHUSDT", 3000.0)]);
+    let mut first = walk.walker();
+    let mut second = walk.walker();
+    for _ in 0..100 {
+        assert_eq!(first.step(), second.step());
+    }
+}
+
+#[tokio::test]
+async fn disconnects_after_scripted_number_of_frames() {
+    let exchange = MockExchange::start(MockExchangeConfig {
+        disconnect_after: Some(2),
+        ..MockExchangeConfig::default()
+    })
+    .await
+    .unwrap();
+    let mut client = connect(&exchange).await;
+    subscribe(&mut client, &["BTCUSDT"]).await;
+
+    for _ in 0..3 {
+        exchange.send_tick(MockTick::new("BTCUSDT", "1.0", "1.1"));
+    }
+    assert!(next_message(&mut client).await.is_text());
+    assert!(next_message(&mut client).await.is_text());
+    assert_eq!(close_code(&next_message(&mut client).await), Some(1000));
+    assert_eq!(exchange.stats().disconnects, 1);
+}
+
+#[tokio::test]
+async fn disconnect_all_closes_every_connection() {
+    let exchange = MockExchange::start(MockExchangeConfig::default())
+        .await
+        .unwrap();
+    let mut first = connect(&exchange).await;
+    let mut second = connect(&exchange).await;
+    subscribe(&mut first, &["BTCUSDT"]).await;
+    subscribe(&mut second, &["BTCUSDT"]).await;
+
+    exchange.disconnect_all();
+    assert_eq!(close_code(&next_message(&mut first).await), Some(1000));
+    assert_eq!(close_code(&next_message(&mut second).await), Some(1000));
+    assert_eq!(exchange.stats().disconnects, 2);
+}
+
+#[tokio::test]
+async fn slow_consumer_is_disconnected() {
+    let exchange = MockExchange::start(MockExchangeConfig {
+        frame_delay: Duration::from_millis(50),
+        buffer: 4,
+        slow_consumer: SlowConsumer::Disconnect,
+        ..MockExchangeConfig::default()
+    })
+    .await
+    .unwrap();
+    let mut client = connect(&exchange).await;
+    subscribe(&mut client, &["BTCUSDT"]).await;
+
+    for _ in 0..50 {
+        exchange.send_tick(MockTick::new("BTCUSDT", "1.0", "1.1"));
+    }
+    let code = loop {
+        let message = next_message(&mut client).await;
+        if message.is_close() {
+            break close_code(&message);
+        }
+    };
+    assert_eq!(code, Some(1008));
+    let stats = exchange.stats();
+    assert_eq!(stats.slow_consumers, 1);
+    assert!(stats.frames_dropped > 0);
+}
+
+#[tokio::test]
+async fn answers_pings_and_sends_its_own() {
+    let exchange = MockExchange::start(MockExchangeConfig {
+        ping_interval: Some(Duration::from_millis(20)),
+        ..MockExchangeConfig::default()
+    })
+    .await
+    .unwrap();
+    let mut client = connect(&exchange).await;
+
+    client.send(Message::Ping(b"hi".to_vec())).await.unwrap();
+    let (mut pong, mut ping) = (false, false);
+    while !(pong && ping) {
+        let message = tokio::time::timeout(WAIT, client.next())
+            .await
+            .expect("no ping or pong from mock exchange")
+            .unwrap()
+            .unwrap();
+        match message {
+            Message::Pong(payload) => {
+                assert_eq!(payload, b"hi");
+                pong = true;
+            }
+            Message::Ping(_) => ping = true,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    assert_eq!(exchange.stats().pings, 1);
+}
+
+// весь путь кадра: WebSocketClient -> OUTCOMING_QUEUE -> book_ticker::parse -> наблюдатель
+#[tokio::test]
+async fn websocket_client_delivers_parsed_updates_to_observers() {
+    let exchange = MockExchange::start(MockExchangeConfig {
+        feed: Feed::Scripted {
+            ticks: vec![MockTick::new("BTCUSDT", "60000.1", "60000.2")],
+            repeat: true,
+        },
+        tick_interval: Duration::from_millis(1),
+        ..MockExchangeConfig::default()
+    })
+    .await
+    .unwrap();
+
+    let observable = Arc::new(Observable::new());
+    let (sender, received) = mpsc::channel();
+    let sender = std::sync::Mutex::new(sender);
+    let handle = observable.add_observer(Box::new(move |update| {
+        let _ = sender.lock().unwrap().send(update.clone());
+    }));
+    // читатель кадров, как в ws run; поток остается ждать очередь до конца теста
+    let reader = Arc::clone(&observable);
+    thread::spawn(move || {
+        while let Some((text, stamp)) = OUTCOMING_QUEUE.pop_stamped() {
+            if let Some(update) = book_ticker::parse(&text, stamp.wall_us) {
+                reader.notify_observers(update);
+            }
+        }
+    });
+
+    let client = WebSocketClient::new(&exchange.url()).await.unwrap();
+    client.send_message(&book_ticker::subscribe_message(&["BTCUSDT".to_string()], 1));
+
+    let update = tokio::task::spawn_blocking(move || received.recv_timeout(WAIT))
+        .await
+        .unwrap()
+        .expect("no update from websocket client");
+    assert_eq!(update.symbol, "BTCUSDT");
+    assert_eq!(
+        (update.bid.as_str(), update.ask.as_str()),
+        ("60000.1", "60000.2")
+    );
+    assert!(update.exchange_ts.is_some());
+    assert!(update.recv_ts > 0);
+    assert_eq!(exchange.stats().subscriptions, 1);
+
+    client.close().unwrap();
+    observable.remove_observer(&handle);
+}
@@ -0,0 +1,149 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use ws::brain::fixed;
+use ws::brain::market::Market;
+use ws::brain::observer::PriceUpdate;
+use ws::brain::warmup::WarmupPolicy;
+use ws::brain::BrainConfig;
+use ws::recorder::format::{Record, RecordFormat};
+use ws::replay::report::ReplayReport;
+use ws::replay::{backtest, BacktestConfig, ReplaySpeed};
+
+fn update(symbol: &str, bid: &str, ask: &str, recv_ts: i64) -> PriceUpdate {
+    PriceUpdate {
+        symbol: symbol.to_string(),
+        bid: bid.to_string(),
+        ask: ask.to_string(),
+        bid_qty: "100".to_string(),
+        ask_qty: "100".to_string(),
+        exchange_ts: None,
+        recv_ts,
+        trace: Default::default(),
+    }
+}
+
+fn brain_config() -> BrainConfig {
+    BrainConfig {
+        rate: 0.5,
+        signal_min_change: 0.0,
+        signal_cooldown: Duration::ZERO,
+        signal_top_k: 10,
+        signal_exclusive_pairs: false,
+        capital: 0.0,
+        capital_asset: "USDT".to_string(),
+        max_price_age: None,
+        warmup: WarmupPolicy {
+            min_coverage: 0.0,
+            timeout: None,
+        },
+        calc_scale: fixed::SCALE,
+        earn_scale: 4,
+        fee_rate: 0.001,
+        symbols: None,
+    }
+}
+
+// ETHBTC на один тик дороже, чем через USDT: обе стороны цикла USDT - ETH - BTC открываются и закрываются
+fn updates() -> [PriceUpdate; 4] {
+    [
+        update("BTCUSDT", "60000", "60001", 1_000_000),
+        update("ETHUSDT", "3000", "3000.1", 1_001_000),
+        update("ETHBTC", "0.051", "0.0511", 1_002_000),
+        update("ETHBTC", "0.05", "0.0501", 1_003_000),
+    ]
+}
+
+fn write_records<W: Write>(out: &mut W, format: RecordFormat, updates: &[PriceUpdate]) {
+    format.write_header(out).unwrap();
+    for update in updates {
+        format
+            .write_record(out, &Record::Update(update.clone()))
+            .unwrap();
+        out.flush().unwrap();
+    }
+}
+
+fn record_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ws-replay-{}-{}", name, std::process::id()))
+}
+
+// движок глобальный: бэктесты одного процесса идут по очереди
+static ENGINE: Mutex<()> = Mutex::new(());
+
+fn run_backtest(path: &Path) -> ReplayReport {
+    let _engine = ENGINE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let settings =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/two_bases/settings.ini");
+    let market = Market::load(settings, None, 0.001).unwrap();
+    let report = backtest(
+        path,
+        &market,
+        &brain_config(),
+        &BacktestConfig {
+            speed: ReplaySpeed::Max,
+            fee_rate: 0.001,
+            notional: 100.0,
+        },
+    )
+    .unwrap();
+    fs::remove_file(path).unwrap();
+    report
+}
+
+#[test]
+fn backtest_processes_every_update_and_closes_opportunities() {
+    let path = record_path("full.jsonl");
+    let mut out = BufWriter::new(File::create(&path).unwrap());
+    write_records(&mut out, RecordFormat::Jsonl, &updates());
+    drop(out);
+
+    let report = run_backtest(&path);
+    assert_eq!(report.stats.updates, updates().len() as u64);
+    assert!(!report.stats.truncated);
+    assert_eq!(report.opportunities, 2, "{}", report);
+    for triangle in &report.triangles {
+        assert!(["USDT", "BTC"].contains(&triangle.key.start.as_str()));
+        assert_eq!(triangle.opportunities, 1);
+        // закрылась на следующем тике ETHBTC, через 1 мс
+        assert_eq!(triangle.persistence_ms.count, 1);
+        assert_eq!(triangle.persistence_ms.max, 1.0);
+    }
+}
+
+// процесс записи убит: последняя запись оборвана посередине, отчет по прочитанному
+#[test]
+fn backtest_stops_at_a_cut_off_last_record() {
+    for format in [RecordFormat::Jsonl, RecordFormat::Binary] {
+        let path = record_path(&format!("cut.{}", format.extension()));
+        let mut bytes = Vec::new();
+        write_records(&mut bytes, format, &updates());
+        fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+
+        let report = run_backtest(&path);
+        assert!(report.stats.truncated, "{:?}", format);
+        assert_eq!(report.stats.updates, 3, "{:?}", format);
+        // возможность открылась на третьем тике и не успела закрыться
+        assert_eq!(report.opportunities, 2, "{:?}: {}", format, report);
+    }
+}
+
+// zstd без завершающего фрейма: сброшенные блоки читаются, конец фрейма - конец данных
+#[test]
+fn backtest_reads_an_unfinished_zstd_frame() {
+    let path = record_path("unfinished.bin.zst");
+    let mut encoder = zstd::Encoder::new(File::create(&path).unwrap(), 3).unwrap();
+    write_records(&mut encoder, RecordFormat::Binary, &updates());
+    // без finish: файл обрывается так же, как у убитого процесса
+    drop(encoder);
+
+    let report = run_backtest(&path);
+    assert!(report.stats.truncated);
+    assert_eq!(report.stats.updates, updates().len() as u64);
+    assert_eq!(report.opportunities, 2, "{}", report);
+}
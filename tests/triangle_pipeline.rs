@@ -0,0 +1,306 @@
+use proptest::prelude::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use ws::brain::fixed::Fixed;
+use ws::brain::graph::{
+    clearing, create_triangles, find_differences, re_cycles, AssetRole, CurrencyGraph,
+};
+use ws::brain::market::Market;
+use ws::brain::triangle::TriangleKey;
+use ws::brain::{depth_first_search, get_nodes_by_label, remove_duplicates, triangle_sorting};
+use ws::brain_sets::{AltCurrency, BaseCurrency, ParsedPairs, Template};
+use ws::cli::describe;
+use ws::symbols::SymbolRegistry;
+
+const BASES: [&str; 4] = ["USDT", "BTC", "ETH", "BNB"];
+const ALTS: [&str; 5] = ["SOL", "XRP", "ADA", "DOT", "TRX"];
+
+// рынок из настроек и рабочих пар; у каждой пары известны базовая и котируемая валюты
+#[derive(Debug)]
+struct Generated {
+    base: Vec<BaseCurrency>,
+    alt: Vec<AltCurrency>,
+    pairs: Vec<(String, String)>,
+}
+
+impl Generated {
+    fn clean(&self) -> Vec<ParsedPairs> {
+        let template = Template { ixs: 0, ixe: 0 };
+        self.pairs
+            .iter()
+            .map(|(base, quote)| {
+                ParsedPairs::new(
+                    format!("{}{}", base, quote),
+                    template.clone(),
+                    template.clone(),
+                    template.clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn assets(&self) -> HashMap<String, (String, String)> {
+        self.pairs
+            .iter()
+            .map(|(base, quote)| (format!("{}{}", base, quote), (base.clone(), quote.clone())))
+            .collect()
+    }
+}
+
+/*
+Базовые валюты - первые из BASES, alt - любые из ALTS и BASES (базовые тоже
+торгуются между собой). Каждая пара alt-base есть или нет, символ в любой
+ориентации, между двумя валютами не больше одной пары.
+*/
+fn markets() -> impl Strategy<Value = Generated> {
+    let universe = ALTS.len() + BASES.len();
+    (
+        1..=BASES.len(),
+        prop::collection::vec(any::<bool>(), universe),
+        prop::collection::vec((any::<bool>(), any::<bool>()), universe * BASES.len()),
+    )
+        .prop_map(move |(bases, alts, pairs)| {
+            let base: Vec<BaseCurrency> = BASES[..bases]
+                .iter()
+                .map(|symbol| BaseCurrency {
+                    symbol: symbol.to_string(),
+                    percentage: 100.0 / bases as f32,
+                    min_earn: None,
+                })
+                .collect();
+            let alt: Vec<AltCurrency> = ALTS
+                .iter()
+                .chain(BASES.iter())
+                .zip(&alts)
+                .filter(|(_, chosen)| **chosen)
+                .map(|(symbol, _)| AltCurrency {
+                    symbol: symbol.to_string(),
+                })
+                .collect();
+
+            let mut seen: HashSet<BTreeSet<&str>> = HashSet::new();
+            let mut listed = Vec::new();
+            for (i, a) in ALTS.iter().chain(BASES.iter()).enumerate() {
+                for (j, b) in BASES.iter().enumerate() {
+                    let (exists, alt_first) = pairs[i * BASES.len() + j];
+                    if !exists || a == b || !seen.insert(BTreeSet::from([*a, *b])) {
+                        continue;
+                    }
+                    let (first, second) = if alt_first { (a, b) } else { (b, a) };
+                    listed.push((first.to_string(), second.to_string()));
+                }
+            }
+            Generated {
+                base,
+                alt,
+                pairs: listed,
+            }
+        })
+}
+
+// валюты, через которые проходит треугольник, начиная со стартовой
+fn walk(
+    legs: &[(String, String)],
+    start: &str,
+    assets: &HashMap<String, (String, String)>,
+) -> Result<Vec<String>, TestCaseError> {
+    let mut path = vec![start.to_string()];
+    let mut holding = start.to_string();
+    for (symbol, side) in legs {
+        let (base, quote) = assets
+            .get(symbol)
+            .ok_or_else(|| TestCaseError::fail(format!("{} is not a listed pair", symbol)))?;
+        holding = match side.as_str() {
+            "SELL" if holding == *base => quote.clone(),
+            "BUY" if holding == *quote => base.clone(),
+            _ => {
+                return Err(TestCaseError::fail(format!(
+                    "{} {} while holding {}",
+                    side, symbol, holding
+                )))
+            }
+        };
+        path.push(holding.clone());
+    }
+    let end = path.pop();
+    prop_assert_eq!(end.as_deref(), Some(start), "{:?} is not closed", legs);
+    Ok(path)
+}
+
+proptest! {
+    #[test]
+    fn cycles_match_legacy_search(market in markets()) {
+        let graph = CurrencyGraph::build(&market.base, &market.alt, &market.clean(), None, Fixed::ZERO);
+        let cycles: Vec<Vec<&str>> = graph.cycles(3).collect();
+
+        let bases: HashSet<&str> = graph.assets(AssetRole::Base).into_iter().collect();
+        for cycle in &cycles {
+            prop_assert_eq!(cycle.iter().collect::<HashSet<_>>().len(), 3);
+            prop_assert!(cycle.iter().any(|asset| bases.contains(asset)));
+            prop_assert!(graph.path_edges(cycle).is_some());
+        }
+
+        let base_nodes = get_nodes_by_label(graph.graph(), AssetRole::Base);
+        let found = depth_first_search(graph.graph(), &base_nodes, 3).unwrap();
+        for path in &found {
+            prop_assert_eq!(path.len(), 4);
+            prop_assert_eq!(path.first(), path.last());
+        }
+        let legacy: BTreeSet<Vec<&str>> = remove_duplicates(triangle_sorting(found))
+            .into_iter()
+            .collect();
+        let canonical: BTreeSet<Vec<&str>> = cycles
+            .iter()
+            .map(|cycle| {
+                let mut sorted = cycle.clone();
+                sorted.sort_unstable();
+                sorted
+            })
+            .collect();
+        prop_assert_eq!(canonical.len(), cycles.len(), "cycle emitted twice");
+        prop_assert_eq!(legacy, canonical);
+    }
+
+    #[test]
+    fn triangles_are_closed_cycles_of_listed_pairs(market in markets()) {
+        let clean = market.clean();
+        let assets = market.assets();
+        let graph = CurrencyGraph::build(&market.base, &market.alt, &clean, None, Fixed::ZERO);
+        let triangles = create_triangles(&graph, &market.base);
+
+        let bases: HashSet<&str> = market.base.iter().map(|b| b.symbol.as_str()).collect();
+        let expected: usize = graph
+            .cycles(3)
+            .map(|cycle| 2 * cycle.iter().filter(|asset| bases.contains(*asset)).count())
+            .sum();
+        prop_assert_eq!(triangles.len(), expected);
+
+        for (key, legs) in &triangles {
+            prop_assert_eq!(legs.len(), 3);
+            prop_assert!(bases.contains(key.start.as_str()));
+            prop_assert_eq!([&key.a, &key.b, &key.c], [&legs[0].0, &legs[1].0, &legs[2].0]);
+            prop_assert_eq!(&key.d, &legs[0].1);
+
+            let path = walk(legs, &key.start, &assets)?;
+            prop_assert_eq!(path.iter().collect::<HashSet<_>>().len(), 3);
+
+            // обратный обход: те же пары в обратном порядке, стороны противоположные
+            let reversed: Vec<(String, String)> = legs
+                .iter()
+                .rev()
+                .map(|(symbol, side)| {
+                    let flipped = if side == "SELL" { "BUY" } else { "SELL" };
+                    (symbol.clone(), flipped.to_string())
+                })
+                .collect();
+            let reverse_key = TriangleKey::from_legs(&reversed, &key.start).unwrap();
+            prop_assert_eq!(triangles.get(&reverse_key), Some(&reversed));
+
+            // движок считает ноги по ребрам графа: путь валют дает те же ребра
+            let path: Vec<&str> = path.iter().map(String::as_str).collect();
+            prop_assert_eq!(graph.legs(&path), Some(legs.clone()));
+            for (symbol, side) in legs {
+                let edge = graph.leg(symbol, side).unwrap();
+                prop_assert_eq!((&edge.pair.symbol, edge.side.as_str()), (symbol, side.as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn clearing_keeps_exactly_the_cycle_pairs(market in markets()) {
+        let clean = market.clean();
+        let graph = CurrencyGraph::build(&market.base, &market.alt, &clean, None, Fixed::ZERO);
+        let cycles: Vec<Vec<&str>> = graph.cycles(3).collect();
+
+        let need = re_cycles(&graph, &cycles);
+        prop_assert_eq!(need.len(), cycles.len());
+        let in_cycles: HashSet<&str> = need
+            .iter()
+            .inspect(|pairs| assert_eq!(pairs.len(), 3))
+            .flatten()
+            .map(|pair| pair.symbol.as_str())
+            .collect();
+
+        let pairs = clearing(&clean, &find_differences(&clean, &need));
+        let expected: Vec<String> = clean
+            .iter()
+            .filter(|pair| in_cycles.contains(pair.symbol.as_str()))
+            .map(|pair| pair.symbol.clone())
+            .collect();
+        prop_assert_eq!(pairs, expected);
+    }
+}
+
+/*
+Снимки ws graph для настроек из tests/fixtures/<name>: settings.ini и, если
+есть, exchange_info.json. UPDATE_GOLDEN=1 перезаписывает expected.txt.
+*/
+fn golden(name: &str) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    let exchange_info = dir.join("exchange_info.json");
+    let registry = exchange_info
+        .exists()
+        .then(|| SymbolRegistry::load(&exchange_info).unwrap());
+    let market = Market::load(dir.join("settings.ini"), registry.as_ref(), 0.001).unwrap();
+    let actual = describe(&market);
+
+    let expected_path = dir.join("expected.txt");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&expected_path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&expected_path).unwrap();
+    assert_eq!(
+        actual, expected,
+        "{} differs from the snapshot, rerun with UPDATE_GOLDEN=1 if the change is intended",
+        name
+    );
+}
+
+#[test]
+fn golden_two_bases() {
+    golden("two_bases");
+}
+
+#[test]
+fn golden_exchange_info() {
+    golden("exchange_info");
+}
+
+#[test]
+fn golden_no_triangles() {
+    golden("no_triangles");
+}
+
+// без exchangeInfo BTCBUSD склеивается и из BTCB+USD, и из BTC+BUSD: такой пары нет
+#[test]
+fn ambiguous_guessed_symbol_is_not_an_edge() {
+    let base = ["USD", "BUSD"]
+        .iter()
+        .map(|symbol| BaseCurrency {
+            symbol: symbol.to_string(),
+            percentage: 50.0,
+            min_earn: None,
+        })
+        .collect();
+    let alt = ["BTC", "BTCB", "ETH"]
+        .iter()
+        .map(|symbol| AltCurrency {
+            symbol: symbol.to_string(),
+        })
+        .collect();
+    let market = Market::new(base, alt, None, 0.001);
+
+    assert!(market.graph.leg("BTCBUSD", "SELL").is_none());
+    assert!(!market.pairs.iter().any(|pair| pair == "BTCBUSD"));
+    let edge = market.graph.leg("ETHUSD", "SELL").unwrap();
+    assert!(edge.pair.guessed);
+    assert_eq!(
+        (edge.pair.base.as_str(), edge.pair.quote.as_str()),
+        ("ETH", "USD")
+    );
+}